@@ -4,7 +4,7 @@ pub mod ui;
 use crate::app::logic::bezier;
 use crate::app::logic::l_system;
 use crate::app::logic::midpoint_displacement;
-use egui::Pos2;
+use crate::app::logic::transform3d;
 
 // --------------------------------------------------
 // Базовое определение приложения
@@ -37,10 +37,22 @@ pub struct FractalsApp {
     md_show_steps: bool,
     md_current_area: Option<egui::Rect>,
 
-    // Поля для сплайнов Безье
-    bezier_segments: usize,
-    bezier_show_points: bool,
-    bezier_show_control: bool,
+    // Поля для L-систем
+    ls_camera: transform3d::Camera,
+    ls_memory_budget_mb: f64,
+    ls_pending_growth: Option<l_system::Growth>,
+    ls_target_iteration: usize,
+    ls_job: Option<logic::LsystemJob>,
+    ls_fractint: Option<(std::path::PathBuf, Vec<l_system::FractintEntry>)>,
+    ls_fractint_filter: String,
+    ls_load_errors: Option<logic::LsystemLoadErrors>,
+    ls_error: Option<String>,
+    ls_warnings: Vec<l_system::LWarning>,
+    ls_presets_filter: String,
+    ls_presets_dir: Option<std::path::PathBuf>,
+    ls_user_presets: Vec<std::path::PathBuf>,
+    ls_thumbnails: std::collections::HashMap<logic::ThumbnailKey, logic::Thumbnail>,
+    ls_thumbnail_worker: Option<logic::ThumbnailWorker>,
 
     // Другие необходимые поля
     painter_width: f32,
@@ -55,12 +67,12 @@ pub struct FractalsApp {
 
     // Для управления
     selected_point: Option<usize>,
-    drag_prev_pos: Option<Pos2>,
 }
 
 impl Default for FractalsApp {
     fn default() -> Self {
         let md_roughness = 0.5;
+        let ls_presets_dir = l_system::default_user_dir();
         Self {
             fractal_type: FractalType::LSystem,
             instrument: Instrument::None,
@@ -71,10 +83,25 @@ impl Default for FractalsApp {
             md_show_steps: false,
             md_current_area: None,
 
-            // Сплайны Безье по умолчанию
-            bezier_segments: 50,
-            bezier_show_points: true,
-            bezier_show_control: true,
+            // L-системы по умолчанию
+            ls_camera: transform3d::Camera::default(),
+            ls_memory_budget_mb: 512.0,
+            ls_pending_growth: None,
+            ls_target_iteration: 1,
+            ls_job: None,
+            ls_fractint: None,
+            ls_fractint_filter: String::new(),
+            ls_load_errors: None,
+            ls_error: None,
+            ls_warnings: Vec::new(),
+            ls_presets_filter: String::new(),
+            ls_user_presets: ls_presets_dir
+                .as_deref()
+                .map(l_system::list_user_dir)
+                .unwrap_or_default(),
+            ls_presets_dir,
+            ls_thumbnails: std::collections::HashMap::new(),
+            ls_thumbnail_worker: None,
 
             // Другие поля
             painter_width: 900.0,
@@ -90,7 +117,6 @@ impl Default for FractalsApp {
 
             // Для управления
             selected_point: None,
-            drag_prev_pos: None,
         }
     }
 }
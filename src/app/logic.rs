@@ -1,10 +1,12 @@
 use crate::app::FractalsApp;
 use egui::{Color32, Painter, Pos2, Response, Ui};
+use std::sync::Arc;
 
 pub mod bezier;
 pub mod l_system;
 pub mod midpoint_displacement;
 pub mod transform2d;
+pub mod transform3d;
 pub mod utils;
 
 // --------------------------------------------------
@@ -55,7 +57,7 @@ impl FractalsApp {
         match self.fractal_type {
             crate::app::FractalType::LSystem => {
                 if let Some(ls) = &self.lsystem {
-                    ls.draw(painter, area, 5.0);
+                    ls.draw(painter, area, 5.0, &self.ls_camera);
                 } else {
                     painter.text(
                         area.center(),
@@ -67,7 +69,7 @@ impl FractalsApp {
                 }
             }
             crate::app::FractalType::MidpointDisplacement => {
-                let area_changed = self.md_current_area.map_or(true, |prev_area| {
+                let area_changed = self.md_current_area.is_none_or(|prev_area| {
                     (prev_area.width() - area.width()).abs() > 0.0
                         || (prev_area.height() - area.height()).abs() > 0.0
                 });
@@ -114,19 +116,30 @@ impl FractalsApp {
     pub fn handle_input(&mut self, response: &Response) {
         self.handle_click(response);
         self.handle_drag(response);
+        self.handle_camera_drag(response);
+    }
+
+    /// Обработать вращение камеры L-системы перетаскиванием.
+    fn handle_camera_drag(&mut self, response: &Response) {
+        if self.fractal_type != crate::app::FractalType::LSystem {
+            return;
+        }
+
+        if response.dragged_by(egui::PointerButton::Primary) {
+            let delta = response.drag_delta();
+            self.ls_camera.yaw =
+                (self.ls_camera.yaw + delta.x * 0.5 + 180.0).rem_euclid(360.0) - 180.0;
+            self.ls_camera.pitch = (self.ls_camera.pitch - delta.y * 0.5).clamp(-90.0, 90.0);
+        }
     }
 
     /// Обработать клики по холсту.
     fn handle_click(&mut self, response: &Response) {
-        if response.clicked_by(egui::PointerButton::Primary) {
-            if let Some(pos) = response.hover_pos() {
-                match self.fractal_type {
-                    crate::app::FractalType::BezierSpline => {
-                        self.handle_bezier_click(pos);
-                    }
-                    _ => {}
-                }
-            }
+        if response.clicked_by(egui::PointerButton::Primary)
+            && let Some(pos) = response.hover_pos()
+            && self.fractal_type == crate::app::FractalType::BezierSpline
+        {
+            self.handle_bezier_click(pos);
         }
     }
 
@@ -143,12 +156,12 @@ impl FractalsApp {
 
         // Если сейчас идёт drag ЛКМ
         if response.dragged_by(egui::PointerButton::Primary) {
-            if let Some(pos) = response.hover_pos() {
-                if let Some(idx) = self.selected_point {
-                    // Перемещаем точку по текущей позиции курсора
-                    self.bezier_curve.move_point(idx, pos);
-                    self.point_count = self.bezier_curve.points.len();
-                }
+            if let Some(pos) = response.hover_pos()
+                && let Some(idx) = self.selected_point
+            {
+                // Перемещаем точку по текущей позиции курсора
+                self.bezier_curve.move_point(idx, pos);
+                self.point_count = self.bezier_curve.points.len();
             }
         } else {
             // Если drag закончен/не идёт — сбросим выделение
@@ -189,17 +202,311 @@ impl FractalsApp {
 // Методы для работы с фракталами
 // --------------------------------------------------
 
+/// Построение L-системы в фоновом потоке.
+pub struct LsystemJob {
+    /// Поток, который вернёт построенную L-систему (или ничего, если построение отменили).
+    handle: std::thread::JoinHandle<Option<l_system::Lsystem>>,
+    /// Ход построения.
+    progress: Arc<l_system::Progress>,
+    /// Итерация, до которой строится L-система.
+    target: usize,
+}
+
+impl LsystemJob {
+    /// Доля выполненной работы от 0 до 1.
+    pub fn fraction(&self) -> f32 {
+        self.progress.fraction()
+    }
+
+    /// Итерация, до которой строится L-система.
+    pub fn target(&self) -> usize {
+        self.target
+    }
+}
+
+/// L-система, для которой строится миниатюра в меню Presets.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ThumbnailKey {
+    /// Номер во встроенной библиотеке.
+    Preset(usize),
+    /// Файл из папки пользователя.
+    File(std::path::PathBuf),
+}
+
+/// Миниатюра L-системы в меню Presets.
+pub enum Thumbnail {
+    /// Строится в фоновом потоке.
+    Pending,
+    /// Построена.
+    Ready(Box<l_system::Lsystem>),
+    /// Конфигурацию не удалось разобрать.
+    Failed,
+}
+
+/// Фоновый поток, который по очереди строит миниатюры для меню Presets.
+pub struct ThumbnailWorker {
+    /// Очередь миниатюр, которые нужно построить.
+    requests: std::sync::mpsc::Sender<ThumbnailKey>,
+    /// Построенные миниатюры (None, если конфигурацию не удалось разобрать).
+    results: std::sync::mpsc::Receiver<(ThumbnailKey, Option<Box<l_system::Lsystem>>)>,
+}
+
+impl ThumbnailWorker {
+    /// Запустить поток. После каждой миниатюры он просит ctx перерисовать окно.
+    fn new(ctx: egui::Context) -> Self {
+        let (requests, worker_requests) = std::sync::mpsc::channel::<ThumbnailKey>();
+        let (worker_results, results) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            for key in worker_requests {
+                let lsystem = build_thumbnail(&key);
+                if worker_results.send((key, lsystem)).is_err() {
+                    break;
+                }
+                ctx.request_repaint();
+            }
+        });
+        Self { requests, results }
+    }
+}
+
+/// Построить миниатюру L-системы key. У библиотеки Fractint миниатюра - её первая L-система.
+fn build_thumbnail(key: &ThumbnailKey) -> Option<Box<l_system::Lsystem>> {
+    let config = match key {
+        ThumbnailKey::Preset(index) => l_system::PRESETS[*index].to_config(),
+        ThumbnailKey::File(path) if is_fractint_library(path) => {
+            l_system::read_fractint_library(path).and_then(|entries| match entries.first() {
+                Some(entry) => entry.to_config(),
+                None => Err("в библиотеке нет L-систем".into()),
+            })
+        }
+        ThumbnailKey::File(path) => l_system::Parser::new().parse_l_system(path),
+    };
+    config
+        .ok()
+        .map(|config| Box::new(l_system::Lsystem::thumbnail(config)))
+}
+
+/// Является ли файл path библиотекой L-систем Fractint (`.l`).
+fn is_fractint_library(path: &std::path::Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("l"))
+}
+
+/// Ошибки, найденные при загрузке файла L-системы.
+pub struct LsystemLoadErrors {
+    /// Имя файла.
+    pub file_name: String,
+    /// Строки файла (чтобы показать, где ошибка).
+    pub lines: Vec<String>,
+    /// Сообщения об ошибках и фрагменты файла, к которым они относятся (если известны).
+    pub errors: Vec<(String, Option<l_system::Span>)>,
+}
+
+impl LsystemLoadErrors {
+    /// Собрать ошибки загрузки файла path.
+    fn new(path: &std::path::Path, err: &(dyn std::error::Error + 'static)) -> Self {
+        let file_name = path.file_name().map_or_else(
+            || path.display().to_string(),
+            |name| name.to_string_lossy().into(),
+        );
+        let text = std::fs::read_to_string(path).unwrap_or_default();
+        Self::from_text(file_name, &text, err)
+    }
+
+    /// Собрать ошибки разбора текста text файла file_name.
+    fn from_text(file_name: String, text: &str, err: &(dyn std::error::Error + 'static)) -> Self {
+        let errors = if let Some(errors) = err.downcast_ref::<l_system::LParseErrors>() {
+            errors
+                .0
+                .iter()
+                .map(|err| (err.to_string(), Some(err.span())))
+                .collect()
+        } else if let Some(err) = err.downcast_ref::<l_system::LParseErr>() {
+            vec![(err.to_string(), Some(err.span()))]
+        } else {
+            err.to_string()
+                .lines()
+                .map(|message| (message.to_string(), None))
+                .collect()
+        };
+        Self {
+            file_name,
+            lines: text.lines().map(String::from).collect(),
+            errors,
+        }
+    }
+}
+
 impl FractalsApp {
     /// Загрузить L-систему из файла.
     pub fn load_lsystem(&mut self) {
         let path = rfd::FileDialog::new()
+            .add_filter("L-system", &["txt", "json", "toml", "l"])
             .add_filter("Text File", &["txt"])
+            .add_filter("JSON", &["json"])
+            .add_filter("TOML", &["toml"])
+            .add_filter("Fractint", &["l"])
             .pick_file();
 
         if let Some(path) = path {
-            let mut parser = l_system::Parser::new();
-            let config = parser.parse_l_system(path).expect("Parse error");
-            self.lsystem = Some(l_system::Lsystem::new(config));
+            self.open_lsystem_file(path);
+        }
+    }
+
+    /// Открыть файл L-системы path.
+    pub fn open_lsystem_file(&mut self, path: std::path::PathBuf) {
+        // в библиотеке Fractint много L-систем: нужную выбирают в отдельном окне
+        if is_fractint_library(&path) {
+            match l_system::read_fractint_library(&path) {
+                Ok(entries) => {
+                    self.ls_fractint_filter.clear();
+                    self.ls_fractint = Some((path, entries));
+                    self.ls_load_errors = None;
+                }
+                Err(err) => self.ls_load_errors = Some(LsystemLoadErrors::new(&path, &*err)),
+            }
+            return;
+        }
+
+        // с ошибками в файле текущая L-система остаётся, а ошибки показываются в окне
+        let mut parser = l_system::Parser::new();
+        match parser.parse_l_system(&path) {
+            Ok(config) => self.set_lsystem(config),
+            Err(err) => self.ls_load_errors = Some(LsystemLoadErrors::new(&path, &*err)),
+        }
+    }
+
+    /// Загрузить L-систему из встроенной библиотеки.
+    pub fn load_preset(&mut self, preset: &l_system::Preset) {
+        match preset.to_config() {
+            Ok(config) => self.set_lsystem(config),
+            Err(err) => {
+                self.ls_load_errors = Some(LsystemLoadErrors::from_text(
+                    preset.file_name.into(),
+                    preset.text,
+                    &*err,
+                ))
+            }
+        }
+    }
+
+    /// Выбрать папку пользователя с L-системами.
+    pub fn choose_presets_dir(&mut self) {
+        if let Some(dir) = rfd::FileDialog::new().pick_folder() {
+            self.ls_presets_dir = Some(dir);
+            self.refresh_user_presets();
+        }
+    }
+
+    /// Перечитать список файлов в папке пользователя.
+    pub fn refresh_user_presets(&mut self) {
+        self.ls_user_presets = self
+            .ls_presets_dir
+            .as_deref()
+            .map(l_system::list_user_dir)
+            .unwrap_or_default();
+        // файлы могли измениться, поэтому их миниатюры строятся заново
+        self.ls_thumbnails
+            .retain(|key, _| !matches!(key, ThumbnailKey::File(_)));
+    }
+
+    /// Миниатюра L-системы.
+    ///
+    /// При первом обращении миниатюра заказывается фоновому потоку (чтобы меню с большой
+    /// папкой не замирало) и до готовности остаётся Thumbnail::Pending.
+    pub fn thumbnail(&mut self, key: ThumbnailKey, ctx: &egui::Context) -> &Thumbnail {
+        if !self.ls_thumbnails.contains_key(&key) {
+            let worker = self
+                .ls_thumbnail_worker
+                .get_or_insert_with(|| ThumbnailWorker::new(ctx.clone()));
+            let thumbnail = match worker.requests.send(key.clone()) {
+                Ok(()) => Thumbnail::Pending,
+                Err(_) => Thumbnail::Failed,
+            };
+            self.ls_thumbnails.insert(key.clone(), thumbnail);
+        }
+        &self.ls_thumbnails[&key]
+    }
+
+    /// Забрать миниатюры, построенные фоновым потоком.
+    pub fn poll_thumbnails(&mut self) {
+        let Some(worker) = &self.ls_thumbnail_worker else {
+            return;
+        };
+        loop {
+            match worker.results.try_recv() {
+                Ok((key, lsystem)) => {
+                    // миниатюры файлов могли сбросить, пока они строились
+                    if let Some(thumbnail) = self.ls_thumbnails.get_mut(&key) {
+                        *thumbnail = lsystem.map_or(Thumbnail::Failed, Thumbnail::Ready);
+                    }
+                }
+                Err(std::sync::mpsc::TryRecvError::Empty) => break,
+                Err(std::sync::mpsc::TryRecvError::Disconnected) => {
+                    // поток завершился с паникой: оставшиеся миниатюры уже не построятся
+                    let message = "Поток построения миниатюр завершился с паникой, \
+                                   часть миниатюр не построена";
+                    self.ls_error = Some(message.to_string());
+                    for thumbnail in self.ls_thumbnails.values_mut() {
+                        if matches!(thumbnail, Thumbnail::Pending) {
+                            *thumbnail = Thumbnail::Failed;
+                        }
+                    }
+                    self.ls_thumbnail_worker = None;
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Загрузить L-систему из открытой библиотеки Fractint.
+    pub fn load_fractint_entry(&mut self, index: usize) {
+        let Some((path, entries)) = &self.ls_fractint else {
+            return;
+        };
+        let Some(entry) = entries.get(index) else {
+            return;
+        };
+        match entry.to_config() {
+            Ok(config) => {
+                self.set_lsystem(config);
+                self.ls_fractint = None;
+            }
+            Err(err) => self.ls_load_errors = Some(LsystemLoadErrors::new(path, &*err)),
+        }
+    }
+
+    /// Сделать L-систему с конфигурацией config текущей.
+    fn set_lsystem(&mut self, config: l_system::LSystemConfig) {
+        self.cancel_lsystem_job();
+        self.ls_load_errors = None;
+        self.ls_warnings = config.validate();
+        let lsystem = l_system::Lsystem::new(config);
+        self.current_iteration = lsystem.cur_iter_num();
+        self.ls_target_iteration = lsystem.cur_iter_num();
+        self.lsystem = Some(lsystem);
+        self.ls_pending_growth = None;
+    }
+
+    /// Сохранить текущую L-систему в файл конфигурации.
+    ///
+    /// Если сохранить не удалось, ошибка показывается в окне.
+    pub fn save_lsystem(&mut self) {
+        let Some(lsystem) = &self.lsystem else {
+            return;
+        };
+        let path = rfd::FileDialog::new()
+            .add_filter("Text File", &["txt"])
+            .save_file();
+
+        if let Some(path) = path
+            && let Err(err) = std::fs::write(&path, lsystem.to_config_text())
+        {
+            self.ls_error = Some(format!(
+                "Не удалось сохранить L-систему в {}: {}",
+                path.display(),
+                err
+            ));
         }
     }
 
@@ -227,11 +534,9 @@ impl FractalsApp {
     pub fn iterate_fractal(&mut self) {
         match self.fractal_type {
             crate::app::FractalType::LSystem => {
-                if let Some(lsystem) = &mut self.lsystem {
-                    lsystem.iter_once();
-                    self.current_iteration = lsystem.cur_iter_num();
+                if let Some(n) = self.lsystem.as_ref().map(|l| l.cur_iter_num() + 1) {
+                    self.request_lsystem_iteration(n);
                 }
-                println!("Итерация L-системы...");
             }
             crate::app::FractalType::MidpointDisplacement => {
                 self.midpoint_displacement.iter_once();
@@ -244,6 +549,87 @@ impl FractalsApp {
         }
     }
 
+    /// Перейти к итерации L-системы n, если прогноз роста укладывается в лимит памяти.
+    ///
+    /// Иначе переход откладывается до подтверждения пользователем.
+    pub fn request_lsystem_iteration(&mut self, n: usize) {
+        if let Some(lsystem) = &self.lsystem
+            && n > lsystem.cur_iter_num()
+            && let Some(target) = lsystem.predict_growth(n - lsystem.cur_iter_num()).last()
+            && target.megabytes() > self.ls_memory_budget_mb
+        {
+            self.ls_pending_growth = Some(*target);
+            return;
+        }
+        self.set_lsystem_iteration(n);
+    }
+
+    /// Перейти к итерации L-системы n без проверки прогноза роста.
+    pub fn set_lsystem_iteration(&mut self, n: usize) {
+        self.ls_pending_growth = None;
+        if let Some(lsystem) = &self.lsystem {
+            let seed = lsystem.seed();
+            self.start_lsystem_job(n, seed);
+        }
+        println!("Итерация L-системы...");
+    }
+
+    /// Задать зерно L-системы и перестроить её на текущей итерации.
+    pub fn set_lsystem_seed(&mut self, seed: u64) {
+        if let Some(lsystem) = &self.lsystem {
+            let n = lsystem.cur_iter_num();
+            self.start_lsystem_job(n, seed);
+        }
+    }
+
+    /// Начать строить L-систему на итерации n с зерном seed в фоновом потоке.
+    ///
+    /// Пока построение идёт, на холсте остаётся прежнее изображение.
+    /// Незаконченное предыдущее построение отменяется.
+    fn start_lsystem_job(&mut self, n: usize, seed: u64) {
+        self.cancel_lsystem_job();
+        let Some(lsystem) = &self.lsystem else {
+            return;
+        };
+
+        let mut fork = lsystem.fork();
+        let progress = Arc::new(l_system::Progress::default());
+        let worker_progress = Arc::clone(&progress);
+        let handle = std::thread::spawn(move || {
+            fork.rebuild(n, seed, &worker_progress).then_some(fork)
+        });
+        self.ls_job = Some(LsystemJob {
+            handle,
+            progress,
+            target: n,
+        });
+    }
+
+    /// Отменить построение L-системы в фоновом потоке (если оно идёт).
+    pub fn cancel_lsystem_job(&mut self) {
+        if let Some(job) = self.ls_job.take() {
+            // поток сам заметит отмену и завершится, ждать его не нужно
+            job.progress.cancel();
+        }
+    }
+
+    /// Забрать L-систему у фонового потока, если он закончил построение.
+    pub fn poll_lsystem_job(&mut self) {
+        if !self.ls_job.as_ref().is_some_and(|job| job.handle.is_finished()) {
+            return;
+        }
+        let job = self.ls_job.take().unwrap();
+        match job.handle.join() {
+            Ok(Some(lsystem)) => {
+                self.current_iteration = lsystem.cur_iter_num();
+                self.ls_target_iteration = lsystem.cur_iter_num();
+                self.lsystem = Some(lsystem);
+            }
+            Ok(None) => println!("Построение L-системы отменено"),
+            Err(_) => println!("Поток построения L-системы завершился с паникой"),
+        }
+    }
+
     /// Сбросить кривые Безье.
     pub fn reset_bezier(&mut self) {
         self.bezier_curve.clear();
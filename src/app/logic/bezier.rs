@@ -192,7 +192,7 @@ impl BezierCurve {
         self.points[index] = new_pos;
 
         // Если index — опорная точка, сдвинем соседние control
-        if index % 3 == 0 {
+        if index.is_multiple_of(3) {
             if index > 0 {
                 self.points[index - 1] += delta;
             }
@@ -1,66 +1,352 @@
-use rand::Rng;
+mod color;
+mod expr;
+mod fractint;
+mod growth;
+mod lexer;
+mod parser;
+mod presets;
+mod progress;
+mod structured;
+mod tables;
+mod validate;
+mod writer;
 
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::app::logic::transform3d::{Camera, Vec3};
 use crate::app::logic::utils;
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::{BufRead, BufReader};
-use std::path::Path;
+use color::{ColorMode, GradientStop};
+use expr::Expr;
+pub use fractint::{FractintEntry, read_fractint_library};
+pub use growth::Growth;
+pub use lexer::{Pos, Span};
+pub use parser::{LParseErr, LParseErrors, Parser};
+pub use presets::{CATEGORIES, PRESETS, Preset, default_user_dir, list_user_dir};
+pub use progress::Progress;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use tables::{RuleTable, Schedule};
+pub use validate::LWarning;
+use writer::NumberSources;
 
 /// Интерпретации символов.
+///
+/// Аргументы-выражения вычисляются над параметрами интерпретируемого модуля.
+#[derive(Clone)]
 enum Actions {
     /// Рисование вперёд на указанное расстояние.
-    Forward(f32),
+    Forward(Expr),
     /// Рисование вперёд на случайное расстояние в указанном диапазоне.
-    ForwardRandom(f32, f32),
+    ForwardRandom(Expr, Expr),
+    /// Перемещение вперёд на указанное расстояние без рисования.
+    Move(Expr),
+    /// Перемещение вперёд на случайное расстояние в указанном диапазоне без рисования.
+    MoveRandom(Expr, Expr),
     /// Поворот на указанный угол (в градусах).
-    Rotate(f32),
-    /// Поворот на случайный угол (в градусах) в указанном диапазоне.
+    Rotate(Expr),
+    /// Поворот на случайный угол (в градусах): угол плюс-минус отклонение.
     RotateRandom(f32, f32),
+    /// Тангаж: наклон направления вверх-вниз на указанный угол (в градусах).
+    Pitch(Expr),
+    /// Крен: поворот вокруг направления движения на указанный угол (в градусах).
+    Roll(Expr),
+    /// Разворот на 180 градусов.
+    TurnAround,
+    /// Задать абсолютное направление движения в плоскости холста (в градусах).
+    SetHeading(Expr),
+    /// Установить толщину рисуемых линий.
+    SetWidth(Expr),
+    /// Умножить длину шагов (FORWARD и MOVE) на указанный коэффициент.
+    ScaleLength(Expr),
+    /// Умножить толщину рисуемых линий на указанный коэффициент.
+    ScaleWidth(Expr),
+    /// Перейти к следующему цвету палитры.
+    NextColor,
+    /// Перейти к предыдущему цвету палитры.
+    PrevColor,
+    /// Перейти к цвету палитры с указанным номером.
+    SetColor(Expr),
     /// Сохранить текущее состояние.
     Save,
     /// Восстановить предыдущее состояние.
     Restore,
+    /// Начать новый закрашенный многоугольник.
+    BeginPolygon,
+    /// Добавить текущую позицию вершиной в открытый многоугольник.
+    PolygonVertex,
+    /// Закончить открытый многоугольник и закрасить его.
+    EndPolygon,
 }
 
-impl ToString for Actions {
-    fn to_string(&self) -> String {
+impl std::fmt::Display for Actions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::Forward(x) => format!("FORWARD {}", x),
-            Self::ForwardRandom(x, y) => format!("FORWARD RANDOM {} {}", x, y),
-            Self::Rotate(x) => format!("ROTATE {}", x),
-            Self::RotateRandom(x, y) => format!("ROTATE RANDOM {} {}", x, y),
-            Self::Save => format!("SAVE"),
-            Self::Restore => format!("RESTORE"),
+            Self::Forward(x) => write!(f, "FORWARD {}", x),
+            Self::ForwardRandom(x, y) => write!(f, "FORWARD RANDOM {} {}", x, y),
+            Self::Move(x) => write!(f, "MOVE {}", x),
+            Self::MoveRandom(x, y) => write!(f, "MOVE RANDOM {} {}", x, y),
+            Self::Rotate(x) => write!(f, "ROTATE {}", x),
+            Self::RotateRandom(x, y) => write!(f, "ROTATE RANDOM {} {}", x - y, x + y),
+            Self::Pitch(x) => write!(f, "PITCH {}", x),
+            Self::Roll(x) => write!(f, "ROLL {}", x),
+            Self::TurnAround => write!(f, "TURN_AROUND"),
+            Self::SetHeading(x) => write!(f, "SET_HEADING {}", x),
+            Self::SetWidth(x) => write!(f, "SET_WIDTH {}", x),
+            Self::ScaleLength(x) => write!(f, "SCALE_LENGTH {}", x),
+            Self::ScaleWidth(x) => write!(f, "SCALE_WIDTH {}", x),
+            Self::NextColor => write!(f, "NEXT_COLOR"),
+            Self::PrevColor => write!(f, "PREV_COLOR"),
+            Self::SetColor(x) => write!(f, "SET_COLOR {}", x),
+            Self::Save => write!(f, "SAVE"),
+            Self::Restore => write!(f, "RESTORE"),
+            Self::BeginPolygon => write!(f, "BEGIN_POLYGON"),
+            Self::PolygonVertex => write!(f, "POLYGON_VERTEX"),
+            Self::EndPolygon => write!(f, "END_POLYGON"),
+        }
+    }
+}
+
+/// Действие вместе с именами параметров модуля, которые читают его аргументы.
+#[derive(Clone)]
+struct ActionDef {
+    /// Имена параметров модуля.
+    params: Vec<String>,
+    /// Само действие.
+    action: Actions,
+}
+
+/// Символ модуля: любой символ Unicode или имя из нескольких символов (`Branch`, `Лист`).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Symbol {
+    /// Одиночный символ.
+    Char(char),
+    /// Имя из нескольких символов.
+    Name(Arc<str>),
+}
+
+impl Symbol {
+    /// Символ с текстом text: одиночный символ, если text состоит из одного символа, иначе имя.
+    pub fn new(text: &str) -> Self {
+        let mut chars = text.chars();
+        match (chars.next(), chars.next()) {
+            (Some(ch), None) => Self::Char(ch),
+            _ => Self::Name(text.into()),
+        }
+    }
+}
+
+impl std::fmt::Display for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Char(ch) => write!(f, "{}", ch),
+            Self::Name(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// Модуль L-системы: символ с (возможно пустым) списком параметров.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Module {
+    /// Символ модуля.
+    pub symbol: Symbol,
+    /// Фактические значения параметров.
+    pub params: Vec<f32>,
+}
+
+impl std::fmt::Display for Module {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.symbol)?;
+        if !self.params.is_empty() {
+            let params: Vec<String> = self.params.iter().map(|x| x.to_string()).collect();
+            write!(f, "({})", params.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+/// Шаблон модуля в левой части продукции: символ и имена формальных параметров.
+#[derive(Clone)]
+struct ModulePattern {
+    /// Символ модуля.
+    symbol: Symbol,
+    /// Имена формальных параметров.
+    params: Vec<String>,
+}
+
+impl ModulePattern {
+    /// Подходит ли модуль под шаблон.
+    ///
+    /// Шаблон без параметров подходит модулю с любым числом параметров,
+    /// иначе число параметров должно совпадать.
+    fn matches(&self, module: &Module) -> bool {
+        self.symbol == module.symbol
+            && (self.params.is_empty() || self.params.len() == module.params.len())
+    }
+}
+
+impl std::fmt::Display for ModulePattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.symbol)?;
+        if !self.params.is_empty() {
+            write!(f, "({})", self.params.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+/// Модуль в правой части продукции: символ и выражения для его параметров.
+#[derive(Clone)]
+struct ModuleTemplate {
+    /// Символ модуля.
+    symbol: Symbol,
+    /// Выражения для параметров.
+    args: Vec<Expr>,
+}
+
+impl ModuleTemplate {
+    /// Построить модуль, вычислив выражения над значениями формальных параметров.
+    fn instantiate(&self, values: &[f32]) -> Module {
+        Module {
+            symbol: self.symbol.clone(),
+            params: self.args.iter().map(|arg| arg.eval(values)).collect(),
         }
     }
 }
 
+impl std::fmt::Display for ModuleTemplate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.symbol)?;
+        if !self.args.is_empty() {
+            let args: Vec<String> = self.args.iter().map(|x| x.to_string()).collect();
+            write!(f, "({})", args.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+/// Записать последовательность модулей (шаблонов) подряд.
+///
+/// Имена из нескольких символов отделяются пробелом от соседних букв и цифр,
+/// чтобы `F` и `Leaf` не слились в `FLeaf`.
+fn format_modules<T: std::fmt::Display>(modules: &[T]) -> String {
+    let mut string = String::new();
+    let mut prev_is_name = false;
+    for module in modules {
+        let module = module.to_string();
+        // имя модуля - всё до скобки с параметрами
+        let is_name = module
+            .split('(')
+            .next()
+            .is_some_and(|s| s.chars().count() > 1);
+        if (prev_is_name || is_name)
+            && string.chars().next_back().is_some_and(is_word_char)
+            && module.chars().next().is_some_and(is_word_char)
+        {
+            string.push(' ');
+        }
+        string += &module;
+        prev_is_name = is_name;
+    }
+    string
+}
+
+/// Может ли символ быть частью имени модуля.
+fn is_word_char(ch: char) -> bool {
+    ch.is_alphanumeric() || ch == '_'
+}
+
+/// Символы в порядке их записи, чтобы файл при сохранении и предупреждения
+/// проверки не зависели от порядка обхода `HashMap`.
+pub(super) fn sorted<'a>(symbols: impl IntoIterator<Item = &'a Symbol>) -> Vec<&'a Symbol> {
+    let mut symbols: Vec<&Symbol> = symbols.into_iter().collect();
+    symbols.sort_by_key(|symbol| symbol.to_string());
+    symbols
+}
+
+/// Продукция (правило переписывания) модуля.
+///
+/// Формальные параметры левого контекста, самого модуля и правого контекста
+/// образуют одно пространство имён (в этом порядке).
+#[derive(Clone)]
+struct Production {
+    /// Левый контекст: модули, которые должны стоять перед заменяемым модулем.
+    left_context: Vec<ModulePattern>,
+    /// Заменяемый модуль.
+    predecessor: ModulePattern,
+    /// Правый контекст: модули, которые должны стоять после заменяемого модуля.
+    right_context: Vec<ModulePattern>,
+    /// Условие применимости продукции.
+    condition: Option<Expr>,
+    /// Модули, на которые заменяется модуль.
+    successor: Vec<ModuleTemplate>,
+    /// Вес продукции при случайном выборе среди всех продукций символа.
+    weight: f32,
+}
+
+impl Production {
+    /// Зависит ли продукция от соседей модуля.
+    fn has_context(&self) -> bool {
+        !self.left_context.is_empty() || !self.right_context.is_empty()
+    }
+}
+
 /// Конфигурация L-системы.
+#[derive(Clone)]
 pub struct LSystemConfig {
     /// Аксиома (начальное состояние) L-системы.
-    axiom: String,
-    /// Правила преобразования символов.
-    rules: HashMap<char, String>,
+    axiom: Vec<Module>,
+    /// Правила преобразования символов (у символа может быть несколько продукций).
+    rules: HashMap<Symbol, Vec<Production>>,
+    /// Именованные таблицы правил в порядке объявления.
+    tables: Vec<RuleTable>,
+    /// Какая таблица правил применяется на каждой итерации.
+    schedule: Schedule,
     /// Действия, привязанные к символам.
-    actions: HashMap<char, Actions>,
+    actions: HashMap<Symbol, ActionDef>,
+    /// Символы, которые пропускаются при сопоставлении контекста.
+    ignore: HashSet<Symbol>,
+    /// Зерно генератора случайных чисел (если не задано, выбирается случайно).
+    seed: Option<u64>,
     /// Начальная толщина рисуемых линий.
     width: f32,
     /// Изменение толщины после каждой нарисованной линии.
     width_delta: f32,
+    /// Угол поворота для `+` и `-` в операциях (в градусах).
+    rotate_angle: f32,
     /// Изначальный угол поворота (в градусах).
     angle: f32,
     /// Начальный цвет линий.
     color: egui::Color32,
-    /// Изменение цвета после каждой нарисованной линии.
-    color_delta: egui::Color32,
+    /// Как меняется цвет линий.
+    color_mode: ColorMode,
+    /// Изменение каналов цвета после каждой нарисованной линии (режим RGB).
+    color_delta: [i16; 3],
+    /// Изменение тона (в градусах), насыщенности и яркости после каждой нарисованной
+    /// линии (режим HSV).
+    hsv_delta: [f32; 3],
+    /// Точки градиента по возрастанию позиций (режимы GRADIENT_*).
+    gradient: Vec<GradientStop>,
+    /// Палитра для действий NEXT_COLOR, PREV_COLOR и SET_COLOR.
+    palette: Vec<egui::Color32>,
+    /// Именованные константы (`DEFINE`) в порядке объявления.
+    ///
+    /// В выражения они уже подставлены, но нужны, чтобы записать конфигурацию обратно.
+    constants: Vec<(String, f32)>,
+    /// Исходная запись чисел, заданных выражениями.
+    sources: NumberSources,
 }
 
-impl ToString for LSystemConfig {
-    fn to_string(&self) -> String {
+impl std::fmt::Display for LSystemConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut string = String::new();
 
         string += "Аксиома: ";
-        string += &self.axiom;
+        string += &format_modules(&self.axiom);
+        string += "\n";
+
+        string += "Угол поворота: ";
+        string += &self.rotate_angle.to_string();
         string += "\n";
 
         string += "Начальный угол: ";
@@ -79,29 +365,357 @@ impl ToString for LSystemConfig {
         string += &format!("{:?}", self.color);
         string += "\n";
 
+        string += "Режим цвета: ";
+        string += &self.color_mode.to_string();
+        string += "\n";
+
         string += "Изменение цвета: ";
         string += &format!("{:?}", self.color_delta);
         string += "\n";
 
+        if self.color_mode == ColorMode::Hsv {
+            string += "Изменение HSV: ";
+            string += &format!("{:?}", self.hsv_delta);
+            string += "\n";
+        }
+
+        if !self.gradient.is_empty() {
+            string += "Градиент: ";
+            let gradient: Vec<String> = self
+                .gradient
+                .iter()
+                .map(|(position, color)| format!("{}: {:?}", position, color))
+                .collect();
+            string += &gradient.join(", ");
+            string += "\n";
+        }
+
+        if !self.constants.is_empty() {
+            string += "Константы: ";
+            let constants: Vec<String> = self
+                .constants
+                .iter()
+                .map(|(name, value)| format!("{} = {}", name, value))
+                .collect();
+            string += &constants.join(", ");
+            string += "\n";
+        }
+
+        if !self.palette.is_empty() {
+            string += "Палитра: ";
+            let palette: Vec<String> = self.palette.iter().map(|c| format!("{:?}", c)).collect();
+            string += &palette.join(", ");
+            string += "\n";
+        }
+
         string += "Правила:\n";
-        for rule in &self.rules {
-            string.push(*rule.0);
-            string += "->";
-            string += rule.1;
-            string.push('\n');
+        let tables = std::iter::once(("", &self.rules))
+            .chain(self.tables.iter().map(|t| (t.name.as_str(), &t.rules)));
+        for (name, rules) in tables {
+            if !name.is_empty() {
+                string += &format!("Таблица {}:\n", name);
+            }
+            for production in rules.values().flatten() {
+                if !production.left_context.is_empty() {
+                    string += &format_modules(&production.left_context);
+                    string += " < ";
+                }
+                string += &production.predecessor.to_string();
+                if !production.right_context.is_empty() {
+                    string += " > ";
+                    string += &format_modules(&production.right_context);
+                }
+                if let Some(condition) = &production.condition {
+                    string += " : ";
+                    string += &condition.to_string();
+                }
+                if production.weight != 1.0 {
+                    string += &format!("-({})", production.weight);
+                }
+                string += "->";
+                string += &format_modules(&production.successor);
+                string.push('\n');
+            }
+        }
+        if !self.tables.is_empty() {
+            string += "Расписание таблиц: ";
+            string += &self.schedule_text();
+            string += "\n";
         }
         string.push('\n');
 
+        if !self.ignore.is_empty() {
+            string += "Игнорируются в контексте: ";
+            let ignore: Vec<String> = self.ignore.iter().map(|s| s.to_string()).collect();
+            string += &ignore.join(" ");
+            string += "\n\n";
+        }
+
         string += "Действия:\n";
-        for rule in &self.actions {
-            string.push(*rule.0);
+        for (symbol, action) in &self.actions {
+            string += &ModulePattern {
+                symbol: symbol.clone(),
+                params: action.params.clone(),
+            }
+            .to_string();
             string += "->";
-            string += &rule.1.to_string();
+            string += &action.action.to_string();
             string.push('\n');
         }
         string.push('\n');
 
-        string
+        write!(f, "{}", string)
+    }
+}
+
+/// Отрезок, нарисованный чертёжником в пространстве.
+struct Segment {
+    /// Начало отрезка.
+    begin: Vec3,
+    /// Конец отрезка.
+    end: Vec3,
+    /// Ширина отрезка.
+    width: f32,
+    /// Цвет отрезка.
+    color: egui::Color32,
+}
+
+/// Закрашенный многоугольник, вершины которого отметил чертёжник.
+struct Polygon {
+    /// Вершины многоугольника.
+    points: Vec<Vec3>,
+    /// Цвет заливки.
+    color: egui::Color32,
+}
+
+/// Текущее состояние чертёжника.
+#[derive(Clone)]
+struct LState {
+    /// Текущая позиция.
+    pos: Vec3,
+    /// Направление движения (H).
+    heading: Vec3,
+    /// Направление влево (L).
+    left: Vec3,
+    /// Направление вверх (U).
+    up: Vec3,
+    /// Текущая толщина рисуемых линий.
+    width: f32,
+    /// Множитель длины шагов.
+    length_scale: f32,
+    /// Текущий цвет рисуемых линий.
+    color: egui::Color32,
+    /// Текущий цвет в HSV (яркость в гамме sRGB, как в палитрах выбора цвета):
+    /// в режиме HSV цвет меняется в нём без потерь на округление.
+    hsv: egui::ecolor::HsvaGamma,
+    /// Номер текущего цвета в палитре.
+    palette_index: usize,
+}
+
+/// Оси чертёжника (H, L, U) для направления в плоскости холста:
+/// "вверх" при этом смотрит от наблюдателя.
+fn heading_frame(angle_degrees: f32) -> (Vec3, Vec3, Vec3) {
+    let (sin, cos) = angle_degrees.to_radians().sin_cos();
+    let heading = Vec3::new(cos, sin, 0.0);
+    let left = Vec3::new(-sin, cos, 0.0);
+    (heading, left, heading.cross(left))
+}
+
+/// Чертёжник, который интерпретирует модули по одному и копит получившееся изображение.
+struct Turtle<'a> {
+    /// Конфигурация L-системы с действиями символов.
+    config: &'a LSystemConfig,
+    /// Генератор для случайных действий.
+    rng: StdRng,
+    /// Текущее состояние.
+    state: LState,
+    /// Сохранённые состояния.
+    state_stack: Vec<LState>,
+    /// Открытые многоугольники: они могут быть вложенными, вершины попадают в последний.
+    polygon_stack: Vec<Polygon>,
+    /// Нарисованные отрезки.
+    lines: Vec<Segment>,
+    /// Перемещения без рисования.
+    moves: Vec<Segment>,
+    /// Закрашенные многоугольники.
+    polygons: Vec<Polygon>,
+}
+
+impl<'a> Turtle<'a> {
+    /// Генератор для случайных действий создаётся от зерна, поэтому повторная
+    /// интерпретация той же строки даёт то же изображение.
+    fn new(config: &'a LSystemConfig, seed: u64) -> Self {
+        let (heading, left, up) = heading_frame(config.angle);
+        Self {
+            config,
+            rng: StdRng::seed_from_u64(seed),
+            state: LState {
+                pos: Vec3::ZERO,
+                heading,
+                left,
+                up,
+                width: config.width,
+                length_scale: 1.0,
+                color: config.color,
+                hsv: egui::ecolor::HsvaGamma::from(config.color),
+                palette_index: 0,
+            },
+            state_stack: Vec::new(),
+            polygon_stack: Vec::new(),
+            lines: Vec::new(),
+            moves: Vec::new(),
+            polygons: Vec::new(),
+        }
+    }
+
+    /// Выполнить действие, привязанное к символу модуля (если оно есть).
+    fn interpret(&mut self, module: &Module) {
+        let Some(action) = self.config.actions.get(&module.symbol) else {
+            return;
+        };
+        let params = &module.params;
+        match &action.action {
+            Actions::Forward(distance) => {
+                let distance = distance.eval(params) * self.state.length_scale;
+                let new_pos = self.state.pos + self.state.heading * distance;
+                self.lines.push(Segment {
+                    begin: self.state.pos,
+                    end: new_pos,
+                    width: self.state.width,
+                    color: self.state.color,
+                });
+                self.state.pos = new_pos;
+                self.state.width = (self.state.width + self.config.width_delta).max(1.0);
+                self.advance_color();
+            }
+            Actions::ForwardRandom(min, max) => {
+                let distance =
+                    self.random_in(min.eval(params), max.eval(params)) * self.state.length_scale;
+                let new_pos = self.state.pos + self.state.heading * distance;
+                self.lines.push(Segment {
+                    begin: self.state.pos,
+                    end: new_pos,
+                    width: self.state.width,
+                    color: self.state.color,
+                });
+                self.state.pos = new_pos;
+                self.state.width = (self.state.width + self.config.width_delta).max(1.0);
+                self.advance_color();
+            }
+            Actions::Move(distance) => {
+                let distance = distance.eval(params) * self.state.length_scale;
+                let new_pos = self.state.pos + self.state.heading * distance;
+                self.moves.push(Segment {
+                    begin: self.state.pos,
+                    end: new_pos,
+                    width: self.state.width,
+                    color: self.state.color,
+                });
+                self.state.pos = new_pos;
+            }
+            Actions::MoveRandom(min, max) => {
+                let distance =
+                    self.random_in(min.eval(params), max.eval(params)) * self.state.length_scale;
+                let new_pos = self.state.pos + self.state.heading * distance;
+                self.moves.push(Segment {
+                    begin: self.state.pos,
+                    end: new_pos,
+                    width: self.state.width,
+                    color: self.state.color,
+                });
+                self.state.pos = new_pos;
+            }
+            Actions::Rotate(angle) => {
+                (self.state.heading, self.state.left) =
+                    Vec3::rotate_pair(self.state.heading, self.state.left, angle.eval(params));
+            }
+            Actions::RotateRandom(angle, delta) => {
+                let angle = self.random_in(angle - delta, angle + delta);
+                (self.state.heading, self.state.left) =
+                    Vec3::rotate_pair(self.state.heading, self.state.left, angle);
+            }
+            Actions::Pitch(angle) => {
+                (self.state.heading, self.state.up) =
+                    Vec3::rotate_pair(self.state.heading, self.state.up, angle.eval(params));
+            }
+            Actions::Roll(angle) => {
+                (self.state.left, self.state.up) =
+                    Vec3::rotate_pair(self.state.left, self.state.up, angle.eval(params));
+            }
+            Actions::TurnAround => {
+                self.state.heading = -self.state.heading;
+                self.state.left = -self.state.left;
+            }
+            Actions::SetHeading(angle) => {
+                (self.state.heading, self.state.left, self.state.up) =
+                    heading_frame(angle.eval(params));
+            }
+            Actions::SetWidth(width) => {
+                self.state.width = width.eval(params);
+            }
+            Actions::ScaleLength(k) => {
+                self.state.length_scale *= k.eval(params);
+            }
+            Actions::ScaleWidth(k) => {
+                self.state.width *= k.eval(params);
+            }
+            Actions::NextColor | Actions::PrevColor | Actions::SetColor(_) => {
+                let palette = &self.config.palette;
+                if !palette.is_empty() {
+                    // номера цветов палитры идут по кругу
+                    let index = match &action.action {
+                        Actions::NextColor => self.state.palette_index as i64 + 1,
+                        Actions::PrevColor => self.state.palette_index as i64 - 1,
+                        Actions::SetColor(index) => index.eval(params).round() as i64,
+                        _ => unreachable!(),
+                    };
+                    self.state.palette_index = index.rem_euclid(palette.len() as i64) as usize;
+                    self.set_color(palette[self.state.palette_index]);
+                }
+            }
+            Actions::Save => {
+                self.state_stack.push(self.state.clone());
+            }
+            Actions::Restore => {
+                if let Some(prev_state) = self.state_stack.pop() {
+                    self.state = prev_state;
+                }
+            }
+            Actions::BeginPolygon => {
+                self.polygon_stack.push(Polygon {
+                    points: Vec::new(),
+                    color: self.state.color,
+                });
+            }
+            Actions::PolygonVertex => {
+                if let Some(polygon) = self.polygon_stack.last_mut() {
+                    polygon.points.push(self.state.pos);
+                }
+            }
+            Actions::EndPolygon => {
+                if let Some(polygon) = self.polygon_stack.pop()
+                    && polygon.points.len() >= 3
+                {
+                    self.polygons.push(polygon);
+                }
+            }
+        }
+    }
+
+    /// Случайное число из диапазона [min, max). У пустого диапазона берётся его начало.
+    fn random_in(&mut self, min: f32, max: f32) -> f32 {
+        if min < max {
+            self.rng.random_range(min..max)
+        } else {
+            min
+        }
+    }
+
+    /// Закончить рисование: отрезки, перемещения и многоугольники.
+    fn finish(mut self) -> (Vec<Segment>, Vec<Segment>, Vec<Polygon>) {
+        let mut lines = std::mem::take(&mut self.lines);
+        self.apply_gradient(&mut lines);
+        (lines, self.moves, self.polygons)
     }
 }
 
@@ -109,167 +723,340 @@ impl ToString for LSystemConfig {
 pub struct Lsystem {
     /// Конфигурация.
     config: LSystemConfig,
-    /// Текущая строка.
-    cur_string: String,
+    /// Текущая строка модулей. Хранится только для контекстных L-систем:
+    /// остальные раскрываются в глубину при интерпретации.
+    cur_string: Option<Vec<Module>>,
     /// Текущая итерация.
     iter: usize,
-    /// Текущие линии (изображение).
-    lines: Vec<utils::Line>,
+    /// Текущие отрезки (изображение в пространстве).
+    lines: Vec<Segment>,
+    /// Перемещения без рисования (нужны только для масштабирования изображения).
+    moves: Vec<Segment>,
+    /// Текущие закрашенные многоугольники.
+    polygons: Vec<Polygon>,
+    /// Зерно, от которого зависят все случайные решения L-системы.
+    seed: u64,
 }
 
 impl Lsystem {
     pub fn new(config: LSystemConfig) -> Self {
+        let seed = config.seed.unwrap_or_else(rand::random);
+        // контексту нужны соседи модуля, поэтому такие строки приходится хранить целиком
+        let has_context = config.all_productions().any(|p| p.has_context());
         let mut l = Self {
-            cur_string: config.axiom.clone(),
+            cur_string: has_context.then(|| config.axiom.clone()),
             iter: 1,
             config,
             lines: Vec::new(),
+            moves: Vec::new(),
+            polygons: Vec::new(),
+            seed,
         };
-        l.update_lines();
+        l.update_lines(&Progress::default());
         l
     }
-    fn update_lines(&mut self) {
-        let mut rng = rand::rng();
-        self.lines.clear();
-        /// Текущее состояние L-системы
-        struct LState {
-            /// Текущая позиция.
-            pos: egui::Pos2,
-            /// Текущий угол поворота (в градусах).
-            angle: f32,
-            /// Текущая толщина рисуемых линий.
-            width: f32,
-            /// Текущий цвет рисуемых линий.
-            color: egui::Color32,
-        }
-
-        let mut state_stack: Vec<LState> = Vec::new();
-        let mut current_state = LState {
-            pos: egui::Pos2::new(0.0, 0.0),
-            angle: self.config.angle,
-            width: self.config.width,
-            color: self.config.color,
+
+    /// Копия L-системы без изображения: её можно достроить в другом потоке.
+    pub fn fork(&self) -> Self {
+        Self {
+            config: self.config.clone(),
+            cur_string: self.cur_string.clone(),
+            iter: self.iter,
+            lines: Vec::new(),
+            moves: Vec::new(),
+            polygons: Vec::new(),
+            seed: self.seed,
+        }
+    }
+
+    /// Получить текущее зерно генератора случайных чисел.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Перейти к итерации n (первая итерация - аксиома).
+    pub fn go_to_iteration(&mut self, n: usize) {
+        self.rebuild(n, self.seed, &Progress::default());
+    }
+
+    /// Построить L-систему на итерации n с зерном seed, сообщая о ходе построения в progress.
+    ///
+    /// Все случайные решения зависят только от зерна, поэтому к ранним итерациям можно
+    /// вернуться, заново построив L-систему от аксиомы. Возвращает false, если построение
+    /// отменили: тогда L-система остаётся недостроенной.
+    pub fn rebuild(&mut self, n: usize, seed: u64, progress: &Progress) -> bool {
+        let n = n.max(1);
+        if n < self.iter || seed != self.seed {
+            self.seed = seed;
+            self.iter = 1;
+            if let Some(string) = &mut self.cur_string {
+                *string = self.config.axiom.clone();
+            }
+        }
+
+        // работа - это переписывание хранимой строки на каждом уровне и интерпретация итоговой
+        let rewritten: f64 = if self.cur_string.is_some() {
+            (self.iter + 1..=n).map(|i| self.expected_modules(i)).sum()
+        } else {
+            0.0
         };
+        progress.start(rewritten + self.expected_modules(n));
 
-        for ch in self.cur_string.chars() {
-            if let Some(action) = self.config.actions.get(&ch) {
-                match action {
-                    Actions::Forward(distance) => {
-                        let new_pos = self.calculate_new_position(
-                            current_state.pos,
-                            current_state.angle,
-                            *distance,
-                        );
-                        self.lines.push(utils::Line {
-                            begin: current_state.pos,
-                            end: new_pos,
-                            width: current_state.width,
-                            color: current_state.color,
-                        });
-                        current_state.pos = new_pos;
-                        current_state.width =
-                            (current_state.width + self.config.width_delta).max(1.0);
-                        current_state.color = self.add_color_delta(current_state.color);
-                    }
-                    Actions::ForwardRandom(min, max) => {
-                        let distance = rng.random_range(*min..*max);
-                        let new_pos = self.calculate_new_position(
-                            current_state.pos,
-                            current_state.angle,
-                            distance,
-                        );
-                        self.lines.push(utils::Line {
-                            begin: current_state.pos,
-                            end: new_pos,
-                            width: current_state.width,
-                            color: current_state.color,
-                        });
-                        current_state.pos = new_pos;
-                        current_state.width =
-                            (current_state.width + self.config.width_delta).max(1.0);
-                        current_state.color = self.add_color_delta(current_state.color);
-                    }
-                    Actions::Rotate(angle) => {
-                        current_state.angle += angle;
-                    }
-                    Actions::RotateRandom(min, max) => {
-                        let angle = rng.random_range(*min..*max);
-                        current_state.angle += angle;
-                    }
-                    Actions::Save => {
-                        state_stack.push(LState {
-                            pos: current_state.pos,
-                            angle: current_state.angle,
-                            width: current_state.width,
-                            color: current_state.color,
-                        });
+        while self.iter < n {
+            if !self.rewrite(progress) {
+                return false;
+            }
+        }
+        self.update_lines(progress)
+    }
+
+    /// Интерпретировать текущую строку чертёжником.
+    ///
+    /// Строки бесконтекстных L-систем не хранятся: аксиома раскрывается в глубину
+    /// до текущей итерации, и получившиеся модули сразу передаются чертёжнику.
+    /// Возвращает false, если построение отменили.
+    fn update_lines(&mut self, progress: &Progress) -> bool {
+        let mut turtle = Turtle::new(&self.config, self.seed);
+        match &self.cur_string {
+            Some(string) => {
+                for module in string {
+                    turtle.interpret(module);
+                    if !progress.advance(1) {
+                        return false;
                     }
-                    Actions::Restore => {
-                        if let Some(prev_state) = state_stack.pop() {
-                            current_state = prev_state;
-                        }
+                }
+            }
+            None => {
+                let mut rngs: Vec<StdRng> = (0..self.iter - 1)
+                    .map(|level| self.level_rng(level))
+                    .collect();
+                for module in &self.config.axiom {
+                    if !self.expand(module, 0, &mut rngs, &mut turtle, progress) {
+                        return false;
                     }
                 }
             }
         }
+        (self.lines, self.moves, self.polygons) = turtle.finish();
+        true
     }
 
-    /// Провести ещё одну итерацию L-системы.
-    pub fn iter_once(&mut self) {
-        let mut new_string = String::new();
-
-        for ch in self.cur_string.chars() {
-            if let Some(replacement) = self.config.rules.get(&ch) {
-                new_string.push_str(replacement);
-            } else {
-                new_string.push(ch);
+    /// Раскрыть модуль с уровня level до текущей итерации и передать результат чертёжнику.
+    ///
+    /// На каждом уровне модули раскрываются слева направо, как и при переписывании
+    /// всей строки, поэтому генераторы уровней выбирают те же продукции.
+    /// Возвращает false, если построение отменили.
+    fn expand(
+        &self,
+        module: &Module,
+        level: usize,
+        rngs: &mut [StdRng],
+        turtle: &mut Turtle,
+        progress: &Progress,
+    ) -> bool {
+        if level < rngs.len()
+            && let Some((production, values)) =
+                self.find_production(std::slice::from_ref(module), 0, level, &mut rngs[level])
+        {
+            for template in &production.successor {
+                let child = template.instantiate(&values);
+                if !self.expand(&child, level + 1, rngs, turtle, progress) {
+                    return false;
+                }
             }
+            return true;
         }
+        // модуль без подходящей продукции не меняется и на следующих уровнях
+        turtle.interpret(module);
+        progress.advance(1)
+    }
+
+    /// Генератор для выбора продукций при переходе с уровня level на следующий.
+    fn level_rng(&self, level: usize) -> StdRng {
+        StdRng::seed_from_u64(self.seed ^ (level as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15))
+    }
+
+    /// Переписать текущую строку по продукциям, не интерпретируя её.
+    ///
+    /// Для бесконтекстных L-систем строка не хранится, и достаточно увеличить номер итерации.
+    /// Возвращает false, если построение отменили.
+    fn rewrite(&mut self, progress: &Progress) -> bool {
+        if let Some(string) = &self.cur_string {
+            let level = self.iter - 1;
+            let mut rng = self.level_rng(level);
+            let mut new_string = Vec::with_capacity(string.len());
+
+            for pos in 0..string.len() {
+                let len = new_string.len();
+                if let Some((production, values)) =
+                    self.find_production(string, pos, level, &mut rng)
+                {
+                    new_string.extend(production.successor.iter().map(|m| m.instantiate(&values)));
+                } else {
+                    new_string.push(string[pos].clone());
+                }
+                if !progress.advance(new_string.len() - len) {
+                    return false;
+                }
+            }
 
-        self.cur_string = new_string;
-        self.update_lines();
+            self.cur_string = Some(new_string);
+        }
         self.iter += 1;
+        true
     }
 
-    /// Следующая позиция чертёжника
-    fn calculate_new_position(
+    /// Найти продукцию для модуля на позиции pos и значения её формальных параметров
+    /// при переходе с уровня level на следующий.
+    ///
+    /// Контекстные продукции, чей контекст совпал, имеют приоритет над бесконтекстными.
+    fn find_production(
         &self,
-        start: egui::Pos2,
-        angle_degrees: f32,
-        distance: f32,
-    ) -> egui::Pos2 {
-        let angle_radians = angle_degrees.to_radians();
-        let dx = distance * angle_radians.cos();
-        let dy = distance * angle_radians.sin();
-        egui::Pos2::new(start.x + dx, start.y + dy)
-    }
-
-    fn add_color_delta(&self, color: egui::Color32) -> egui::Color32 {
-        #[cfg(debug_assertions)]
-        println!("color before delta: {:?}", color);
-
-        let delta = self.config.color_delta;
-        let r = if delta.r() > 0 && color.r() < 255 {
-            (color.r() as i32 + delta.r() as i32).clamp(0, 255) as u8
-        } else {
-            color.r()
-        };
-        let g = if delta.g() > 0 && color.g() < 255 {
-            (color.g() as i32 + delta.g() as i32).clamp(0, 255) as u8
-        } else {
-            color.g()
-        };
-        let b = if delta.b() > 0 && color.b() < 255 {
-            (color.b() as i32 + delta.b() as i32).clamp(0, 255) as u8
-        } else {
-            color.b()
-        };
-        let new_color = egui::Color32::from_rgb(r, g, b);
+        modules: &[Module],
+        pos: usize,
+        level: usize,
+        rng: &mut impl Rng,
+    ) -> Option<(&Production, Vec<f32>)> {
+        let productions = self.config.productions(&modules[pos].symbol, level)?;
 
-        #[cfg(debug_assertions)]
-        println!("color after delta: {:?}", color);
+        let contextual: Vec<(&Production, Vec<f32>)> = productions
+            .iter()
+            .filter(|p| p.has_context())
+            .filter_map(|p| Some((p, self.match_production(p, modules, pos)?)))
+            .collect();
+        if !contextual.is_empty() {
+            return Some(Self::choose_production(contextual, rng));
+        }
+
+        let context_free: Vec<(&Production, Vec<f32>)> = productions
+            .iter()
+            .filter(|p| !p.has_context())
+            .filter_map(|p| Some((p, self.match_production(p, modules, pos)?)))
+            .collect();
+        if context_free.is_empty() {
+            return None;
+        }
+        Some(Self::choose_production(context_free, rng))
+    }
 
-        new_color
+    /// Выбрать одну из продукций символа случайно с учётом их весов.
+    fn choose_production<'a>(
+        mut candidates: Vec<(&'a Production, Vec<f32>)>,
+        rng: &mut impl Rng,
+    ) -> (&'a Production, Vec<f32>) {
+        if candidates.len() > 1 {
+            let total: f32 = candidates.iter().map(|(p, _)| p.weight).sum();
+            let mut choice = rng.random_range(0.0..total);
+            for i in 0..candidates.len() {
+                if choice < candidates[i].0.weight {
+                    return candidates.swap_remove(i);
+                }
+                choice -= candidates[i].0.weight;
+            }
+        }
+        // из-за погрешности округления можно не попасть ни в одну продукцию
+        candidates.pop().unwrap()
+    }
+
+    /// Сопоставить продукцию с модулем на позиции pos и его контекстом.
+    ///
+    /// Возвращает значения формальных параметров, если модуль, контекст и условие подходят.
+    fn match_production(
+        &self,
+        production: &Production,
+        modules: &[Module],
+        pos: usize,
+    ) -> Option<Vec<f32>> {
+        if !production.predecessor.matches(&modules[pos]) {
+            return None;
+        }
+
+        let mut left_modules = Vec::with_capacity(production.left_context.len());
+        let mut left = pos;
+        for pattern in production.left_context.iter().rev() {
+            left = self.prev_context_symbol(modules, left)?;
+            if !pattern.matches(&modules[left]) {
+                return None;
+            }
+            left_modules.push(&modules[left]);
+        }
+        left_modules.reverse();
+
+        let mut right_modules = Vec::with_capacity(production.right_context.len());
+        let mut right = pos;
+        for pattern in &production.right_context {
+            right = self.next_context_symbol(modules, right)?;
+            if !pattern.matches(&modules[right]) {
+                return None;
+            }
+            right_modules.push(&modules[right]);
+        }
+
+        let patterns = production
+            .left_context
+            .iter()
+            .chain(std::iter::once(&production.predecessor))
+            .chain(&production.right_context);
+        let matched = left_modules
+            .into_iter()
+            .chain(std::iter::once(&modules[pos]))
+            .chain(right_modules);
+        let mut values = Vec::new();
+        for (pattern, module) in patterns.zip(matched) {
+            if !pattern.params.is_empty() {
+                values.extend_from_slice(&module.params);
+            }
+        }
+
+        if let Some(condition) = &production.condition
+            && !condition.is_true(&values)
+        {
+            return None;
+        }
+        Some(values)
+    }
+
+    /// Позиция ближайшего слева модуля контекста.
+    ///
+    /// Пропускаются игнорируемые символы и боковые ветви (`SAVE ... RESTORE`),
+    /// а из начала ветви поиск продолжается в родительской ветви.
+    fn prev_context_symbol(&self, modules: &[Module], pos: usize) -> Option<usize> {
+        let mut depth = 0usize;
+        for i in (0..pos).rev() {
+            let symbol = &modules[i].symbol;
+            if self.config.ignore.contains(symbol) {
+                continue;
+            }
+            match self.config.actions.get(symbol).map(|a| &a.action) {
+                Some(Actions::Restore) => depth += 1,
+                Some(Actions::Save) => depth = depth.saturating_sub(1),
+                _ if depth == 0 => return Some(i),
+                _ => {}
+            }
+        }
+        None
+    }
+
+    /// Позиция ближайшего справа модуля контекста.
+    ///
+    /// Пропускаются игнорируемые символы и боковые ветви (`SAVE ... RESTORE`),
+    /// а на конце текущей ветви контекст заканчивается.
+    fn next_context_symbol(&self, modules: &[Module], pos: usize) -> Option<usize> {
+        let mut depth = 0usize;
+        for (i, module) in modules.iter().enumerate().skip(pos + 1) {
+            let symbol = &module.symbol;
+            if self.config.ignore.contains(symbol) {
+                continue;
+            }
+            match self.config.actions.get(symbol).map(|a| &a.action) {
+                Some(Actions::Save) => depth += 1,
+                Some(Actions::Restore) if depth == 0 => return None,
+                Some(Actions::Restore) => depth -= 1,
+                _ if depth == 0 => return Some(i),
+                _ => {}
+            }
+        }
+        None
     }
 
     /// Получить номер текущей итерации.
@@ -277,355 +1064,150 @@ impl Lsystem {
         self.iter
     }
 
-    pub fn draw(&self, painter: &egui::Painter, area: egui::Rect, margin: f32) {
-        utils::draw_lines(&self.lines, painter, area, margin);
+    /// Нарисовать L-систему, спроецировав её через камеру.
+    pub fn draw(&self, painter: &egui::Painter, area: egui::Rect, margin: f32, camera: &Camera) {
+        self.draw_with_width(painter, area, margin, camera, f32::INFINITY);
     }
-}
 
-impl ToString for Lsystem {
-    fn to_string(&self) -> String {
-        format!("Итерация: {}\n", self.iter) + &self.config.to_string()
+    /// Нарисовать миниатюру L-системы: толстые линии на ней слиплись бы,
+    /// поэтому все линии рисуются толщиной не больше 1.
+    pub fn draw_thumbnail(&self, painter: &egui::Painter, area: egui::Rect) {
+        self.draw_with_width(painter, area, 2.0, &Camera::default(), 1.0);
     }
-}
 
-/*
-
-Вид файла конфигурации для L-системы:
-```
-<аксиома> <угол поворота в градусах> <начальное направление в градусах>
-<правило/действие/присвоение 1>
-...
-<правило/действие/присвоение N>
-```
-
-Правила имеют вид `<символ> -> <строка над алфавитом>`
-Действия имеют вид `<символ> -> <операция>`
-Присвоения имеют вид `<переменная> = <значение>` - все они опциональны
-
-Операции имеют следующий вид:
-FORWARD <длина>
-| FORWARD RANDOM <мин. длина> <макс. длина>
-| ROTATE
-| ROTATE RANDOM <дельта-угол>
-| SAVE
-| RESTORE
-
-Переменные могут быть следующие:
-WIDTH
-| WIDTH_DELTA
-| COLOR
-| COLOR_DELTA
-*/
-
-/// Ошибки во время пасринга конфига L-системы.
-#[derive(Debug)]
-pub enum LParseErr {
-    /// В пределах строки количество элементов отличается от ожидаемого.
-    UnexpectedValuesAmount(usize, usize, usize),
-    /// Полученное значение не соответсвует ожидаемому.
-    UnexpectedValue(usize, String, String),
+    /// Нарисовать L-систему с толщиной линий не больше max_width.
+    fn draw_with_width(
+        &self,
+        painter: &egui::Painter,
+        area: egui::Rect,
+        margin: f32,
+        camera: &Camera,
+        max_width: f32,
+    ) {
+        let Some((min, max)) = self
+            .lines
+            .iter()
+            .chain(&self.moves)
+            .map(|s| (s.begin.min(s.end), s.begin.max(s.end)))
+            .chain(
+                self.polygons
+                    .iter()
+                    .flat_map(|p| p.points.iter().map(|&point| (point, point))),
+            )
+            .reduce(|(min_a, max_a), (min_b, max_b)| (min_a.min(min_b), max_a.max(max_b)))
+        else {
+            return;
+        };
+        let center = (min + max) * 0.5;
+        let radius = (max - min).length() * 0.5;
+
+        let project_segments = |segments: &[Segment]| -> Vec<utils::Line> {
+            segments
+                .iter()
+                .map(|s| utils::Line {
+                    begin: camera.project(s.begin, center, radius),
+                    end: camera.project(s.end, center, radius),
+                    width: s.width.min(max_width),
+                    color: s.color,
+                })
+                .collect()
+        };
+        let lines = project_segments(&self.lines);
+        let moves = project_segments(&self.moves);
+        let polygons: Vec<utils::Polygon> = self
+            .polygons
+            .iter()
+            .map(|p| utils::Polygon {
+                points: p
+                    .points
+                    .iter()
+                    .map(|&point| camera.project(point, center, radius))
+                    .collect(),
+                color: p.color,
+            })
+            .collect();
+        utils::draw_shapes(&lines, &moves, &polygons, painter, area, margin);
+    }
 }
 
-impl std::fmt::Display for LParseErr {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        match self {
-            Self::UnexpectedValuesAmount(line, expected, got) => write!(
-                f,
-                "строка {}: ожидалось {} элементов, получено {}",
-                line, expected, got
-            ),
-            Self::UnexpectedValue(line, expected, got) => write!(
-                f,
-                "строка {}: ожидалось {}, получено {}",
-                line, expected, got
-            ),
-        }
+impl std::fmt::Display for Lsystem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Итерация: {}\n{}", self.iter, self.config)
     }
 }
 
-impl std::error::Error for LParseErr {}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-pub struct Parser {
-    cur_line: usize,
-}
+    /// L-система из текста конфигурации.
+    fn lsystem(text: &str) -> Lsystem {
+        Lsystem::new(Parser::new().parse_str(text).unwrap())
+    }
 
-impl Parser {
-    pub fn new() -> Self {
-        Self { cur_line: 0 }
-    }
-
-    /// Простой парсинг файла с конфигурацией L-системы
-    pub fn parse_l_system(
-        &mut self,
-        file_path: impl AsRef<Path>,
-    ) -> Result<LSystemConfig, Box<dyn std::error::Error>> {
-        let op_keywords = vec!["FORWARD", "ROTATE", "SAVE", "RESTORE"];
-        let var_keywords = vec!["WIDTH", "WIDTH_DELTA", "COLOR", "COLOR_DELTA"];
-
-        let file = File::open(file_path)?;
-        let mut reader = BufReader::new(file);
-
-        // <аксиома> <угол поворота в градусах> <начальное направление в градусах>
-        let mut line = String::new();
-        reader.read_line(&mut line)?;
-        self.cur_line += 1;
-        let first_line_elems: Vec<&str> = line.split_whitespace().collect();
-        if first_line_elems.len() != 3 {
-            return Err(Box::new(LParseErr::UnexpectedValuesAmount(
-                self.cur_line,
-                3,
-                first_line_elems.len(),
-            )));
-        }
-        let axiom = first_line_elems[0].into();
-        let rotate_angle: f32 = first_line_elems[1].parse()?;
-        let angle = first_line_elems[2].parse()?;
-
-        // <правило> | <действие> | <присвоение>
-        let mut rules = HashMap::new();
-        let mut actions = HashMap::new();
-        let mut width = 1.0;
-        let mut width_delta = 0.0;
-        let mut color = egui::Color32::BLACK;
-        let mut color_delta = egui::Color32::from_rgb(0, 0, 0);
-        line.clear();
-        while reader.read_line(&mut line)? > 0 {
-            self.cur_line += 1;
-
-            // <символ> -> <строка над алфавитом> | <символ> -> <операция>
-            if line.contains("->") {
-                let elems: Vec<&str> = line.split("->").collect();
-                if elems.len() != 2 {
-                    return Err(Box::new(LParseErr::UnexpectedValuesAmount(
-                        self.cur_line,
-                        3,
-                        elems.len(),
-                    )));
-                }
+    /// Текущая строка контекстной L-системы.
+    fn string(lsystem: &Lsystem) -> String {
+        let modules = lsystem.cur_string.as_ref().unwrap();
+        modules.iter().map(|module| module.to_string()).collect()
+    }
 
-                // <символ>
-                let lv = elems[0].trim();
-                if lv.len() > 1 {
-                    return Err(Box::new(LParseErr::UnexpectedValue(
-                        self.cur_line,
-                        "<одиночный символ>".into(),
-                        lv.into(),
-                    )));
-                }
-                let lv = lv.chars().next().unwrap();
-
-                // <строка над алфавитом> | <операция>
-                let rv = elems[1].trim();
-                if rv.is_empty() {
-                    return Err(Box::new(LParseErr::UnexpectedValue(
-                        self.cur_line,
-                        "<строка над алфавитом> | <операция>".into(),
-                        "''".into(),
-                    )));
-                }
+    /// Отрезки L-системы: начало, конец, ширина и цвет.
+    fn segments(lsystem: &Lsystem) -> Vec<(Vec3, Vec3, f32, egui::Color32)> {
+        let lines = lsystem.lines.iter();
+        lines.map(|s| (s.begin, s.end, s.width, s.color)).collect()
+    }
 
-                // <операция>
-                if op_keywords.iter().any(|keyword| rv.contains(*keyword)) {
-                    if rv.starts_with("FORWARD") {
-                        // <символ> -> FORWARD RANDOM <начало диапазона> <конец диапазона>
-                        if rv.contains("RANDOM") {
-                            let rv_trimmed = rv
-                                .trim_start_matches("FORWARD")
-                                .trim()
-                                .trim_start_matches("RANDOM")
-                                .trim();
-                            let rv_nums: Vec<&str> = rv_trimmed.split_whitespace().collect();
-                            if rv_nums.len() != 2 {
-                                return Err(Box::new(LParseErr::UnexpectedValue(
-                                    self.cur_line,
-                                    "<начало диапазона> <конец диапазона>".into(),
-                                    rv_trimmed.into(),
-                                )));
-                            }
-                            let range_begin: f32 = rv_nums[0].parse()?;
-                            let range_end: f32 = rv_nums[1].parse()?;
-                            actions.insert(lv, Actions::ForwardRandom(range_begin, range_end));
-                        }
-                        // <символ> -> FORWARD <длина>
-                        else {
-                            let rv_trimmed = rv.trim_start_matches("FORWARD").trim();
-                            if rv_trimmed.is_empty() {
-                                return Err(Box::new(LParseErr::UnexpectedValue(
-                                    self.cur_line,
-                                    "<длина>".into(),
-                                    "''".into(),
-                                )));
-                            }
-                            let length: f32 = rv_trimmed.parse()?;
-                            actions.insert(lv, Actions::Forward(length));
-                        }
-                    } else if rv.starts_with("ROTATE") {
-                        // <символ> -> ROTATE RANDOM (- | +) <отклонение>
-                        if rv.contains("RANDOM") {
-                            let mut rv_trimmed = rv
-                                .trim_start_matches("ROTATE")
-                                .trim()
-                                .trim_start_matches("RANDOM")
-                                .trim();
-                            if rv_trimmed.is_empty() {
-                                return Err(Box::new(LParseErr::UnexpectedValue(
-                                    self.cur_line,
-                                    "(- | + ) <отклонение>".into(),
-                                    "''".into(),
-                                )));
-                            }
-                            let mut sign = 1.0;
-                            if rv_trimmed.contains('-') {
-                                sign = -1.0;
-                                rv_trimmed = rv_trimmed.trim_start_matches("-").trim();
-                            } else {
-                                rv_trimmed = rv_trimmed.trim_start_matches("+").trim();
-                            }
-
-                            if rv_trimmed.is_empty() {
-                                return Err(Box::new(LParseErr::UnexpectedValue(
-                                    self.cur_line,
-                                    "<отклонение>".into(),
-                                    "''".into(),
-                                )));
-                            }
-
-                            let delta: f32 = rv_trimmed.parse()?;
-                            actions.insert(
-                                lv,
-                                Actions::RotateRandom(
-                                    sign * rotate_angle - delta,
-                                    sign * rotate_angle + delta,
-                                ),
-                            );
-                        }
-                        // <символ> -> ROTATE (+ | -)
-                        else {
-                            let rv_trimmed = rv.trim_start_matches("ROTATE").trim();
-                            if rv_trimmed.len() != 1 {
-                                return Err(Box::new(LParseErr::UnexpectedValue(
-                                    self.cur_line,
-                                    "+ | -".into(),
-                                    rv_trimmed.into(),
-                                )));
-                            }
-                            let mut angle = rotate_angle;
-                            if rv_trimmed.contains('-') {
-                                angle = -angle;
-                            }
-                            actions.insert(lv, Actions::Rotate(angle));
-                        }
-                    }
-                    // <символ> -> SAVE
-                    else if rv.starts_with("SAVE") {
-                        let rv_trimmed = rv.trim_start_matches("SAVE").trim();
-                        if !rv_trimmed.is_empty() {
-                            return Err(Box::new(LParseErr::UnexpectedValue(
-                                self.cur_line,
-                                "''".into(),
-                                rv_trimmed.into(),
-                            )));
-                        }
-                        actions.insert(lv, Actions::Save);
-                    }
-                    // <символ> -> RESTORE
-                    else if rv.starts_with("RESTORE") {
-                        let rv_trimmed = rv.trim_start_matches("RESTORE").trim();
-                        if !rv_trimmed.is_empty() {
-                            return Err(Box::new(LParseErr::UnexpectedValue(
-                                self.cur_line,
-                                "''".into(),
-                                rv_trimmed.into(),
-                            )));
-                        }
-                        actions.insert(lv, Actions::Restore);
-                    } else {
-                        panic!("Ключевое слово для действия найдено, но почему-то не обработано");
-                    }
-                }
-                // <строка над алфавитом>
-                else {
-                    // <символ> -> <строка над алфавитом> <- но самой проверки алфавита не будет, у меня лапки
-                    rules.insert(lv, rv.into());
-                }
-            }
-            // <присвоение>
-            else if line.contains("=") {
-                let elems: Vec<&str> = line.split("=").collect();
-                if elems.len() != 2 {
-                    return Err(Box::new(LParseErr::UnexpectedValuesAmount(
-                        self.cur_line,
-                        3,
-                        elems.len(),
-                    )));
-                }
+    #[test]
+    fn streaming_matches_string_rewriting() {
+        let config = Parser::new()
+            .parse_str(
+                "X 25 -90\n\
+                 [rules]\n\
+                 X -(1)-> F[+X][-X]FX\n\
+                 X -(2)-> F[-X]FX\n\
+                 F -(3)-> FF\n\
+                 F -> F\n\
+                 [actions]\n\
+                 F -> FORWARD RANDOM 1 2\n\
+                 + -> ROTATE RANDOM + 10\n\
+                 - -> ROTATE -\n\
+                 [ -> SAVE\n\
+                 ] -> RESTORE\n\
+                 [vars]\n\
+                 SEED = 7\n",
+            )
+            .unwrap();
 
-                // <переменная>
-                let lv = elems[0].trim();
-                if !var_keywords.iter().any(|keyword| lv == *keyword) {
-                    return Err(Box::new(LParseErr::UnexpectedValue(
-                        self.cur_line,
-                        "WIDTH | WIDTH_DELTA | COLOR | COLOR_DELTA".into(),
-                        lv.into(),
-                    )));
-                }
+        // бесконтекстная L-система раскрывается в глубину, а с сохранённой строкой
+        // переписывается итерация за итерацией
+        let mut streamed = Lsystem::new(config.clone());
+        let mut rewritten = Lsystem::new(config.clone());
+        assert!(streamed.cur_string.is_none());
+        rewritten.cur_string = Some(config.axiom.clone());
 
-                // <значение>
-                let rv = elems[1].trim();
+        for seed in [7, 42] {
+            streamed.rebuild(5, seed, &Progress::default());
+            rewritten.rebuild(5, seed, &Progress::default());
+            assert!(!segments(&streamed).is_empty());
+            assert_eq!(segments(&streamed), segments(&rewritten));
+        }
+    }
 
-                if lv == "WIDTH" || lv == "WIDTH_DELTA" {
-                    let value: f32 = rv.parse()?;
-                    // WIDTH = <float32>
-                    if lv == "WIDTH" {
-                        width = value;
-                    }
-                    // WIDTH_DELTA = <float32>
-                    else {
-                        width_delta = value;
-                    }
-                } else if lv == "COLOR" || lv == "COLOR_DELTA" {
-                    let rgb: Vec<&str> = rv.get(1..rv.len() - 1).unwrap().split(",").collect();
-                    let value = egui::Color32::from_rgb(
-                        rgb[0]
-                            .trim()
-                            .parse()
-                            .expect(&format!("invalid red: {}", rgb[0])),
-                        rgb[1]
-                            .trim()
-                            .parse()
-                            .expect(&format!("invalid green: {}", rgb[0])),
-                        rgb[2]
-                            .trim()
-                            .parse()
-                            .expect(&format!("invalid blue: {}", rgb[0])),
-                    );
-                    if lv == "COLOR" {
-                        color = value;
-                    } else {
-                        color_delta = value;
-                    }
-                } else {
-                    panic!("Обнаружено присвоение, но неизвестная переменная");
-                }
-            } else {
-                return Err(Box::new(LParseErr::UnexpectedValue(
-                    self.cur_line,
-                    "<правило> | <действие> | <присвоение>".into(),
-                    line.into(),
-                )));
-            }
-            line.clear();
-        }
-
-        Ok(LSystemConfig {
-            axiom,
-            rules,
-            actions,
-            width,
-            width_delta,
-            angle,
-            color,
-            color_delta,
-        })
+    #[test]
+    fn context_skips_ignored_symbols_and_branches() {
+        let rules = "[rules]\nA < B -> C\n[actions]\n[ -> SAVE\n] -> RESTORE\n";
+
+        let mut plain = lsystem(&format!("A+B 90 0\n{}", rules));
+        plain.go_to_iteration(2);
+        assert_eq!(string(&plain), "A+B");
+
+        let mut ignored = lsystem(&format!("A+B 90 0\n{}[vars]\nIGNORE = +\n", rules));
+        ignored.go_to_iteration(2);
+        assert_eq!(string(&ignored), "A+C");
+
+        // боковая ветвь не мешает контексту, а в ветви контекст берётся из родительской
+        let mut branched = lsystem(&format!("A[B]B 90 0\n{}", rules));
+        branched.go_to_iteration(2);
+        assert_eq!(string(&branched), "A[C]C");
     }
 }
@@ -0,0 +1,226 @@
+// --------------------------------------------------
+// Изменение цвета линий от отрезка к отрезку
+// --------------------------------------------------
+
+use super::{Segment, Turtle};
+use egui::ecolor::HsvaGamma;
+
+/// Как меняется цвет линий (переменная COLOR_MODE).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(super) enum ColorMode {
+    /// `RGB`: после каждой линии к каналам цвета прибавляется COLOR_DELTA.
+    #[default]
+    Rgb,
+    /// `HSV`: после каждой линии к тону, насыщенности и яркости прибавляется HSV_DELTA.
+    Hsv,
+    /// `GRADIENT_INDEX`: цвет линии берётся из GRADIENT по её номеру.
+    GradientIndex,
+    /// `GRADIENT_LENGTH`: цвет линии берётся из GRADIENT по длине пути до неё.
+    GradientLength,
+}
+
+/// Ключевые слова режимов цвета.
+pub(super) const COLOR_MODE_KEYWORDS: [&str; 4] =
+    ["RGB", "HSV", "GRADIENT_INDEX", "GRADIENT_LENGTH"];
+
+impl ColorMode {
+    /// Режим по ключевому слову.
+    pub(super) fn from_keyword(keyword: &str) -> Option<Self> {
+        match keyword {
+            "RGB" => Some(Self::Rgb),
+            "HSV" => Some(Self::Hsv),
+            "GRADIENT_INDEX" => Some(Self::GradientIndex),
+            "GRADIENT_LENGTH" => Some(Self::GradientLength),
+            _ => None,
+        }
+    }
+
+    /// Берётся ли цвет линий из градиента.
+    pub(super) fn is_gradient(&self) -> bool {
+        matches!(self, Self::GradientIndex | Self::GradientLength)
+    }
+}
+
+impl std::fmt::Display for ColorMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let keyword = match self {
+            Self::Rgb => "RGB",
+            Self::Hsv => "HSV",
+            Self::GradientIndex => "GRADIENT_INDEX",
+            Self::GradientLength => "GRADIENT_LENGTH",
+        };
+        write!(f, "{}", keyword)
+    }
+}
+
+impl Turtle<'_> {
+    /// Задать текущий цвет (например, из палитры).
+    pub(super) fn set_color(&mut self, color: egui::Color32) {
+        self.state.color = color;
+        self.state.hsv = HsvaGamma::from(color);
+    }
+
+    /// Изменить цвет после нарисованной линии согласно COLOR_MODE.
+    ///
+    /// В режимах градиента цвет здесь не меняется: линии раскрашиваются в finish,
+    /// когда известны их количество и общая длина.
+    pub(super) fn advance_color(&mut self) {
+        match self.config.color_mode {
+            ColorMode::Rgb => {
+                // каналы упираются в 0 и 255 и дальше не меняются
+                let channel =
+                    |value: u8, delta: i16| (i16::from(value) + delta).clamp(0, 255) as u8;
+                let color = self.state.color;
+                let [r, g, b] = self.config.color_delta;
+                self.set_color(egui::Color32::from_rgb(
+                    channel(color.r(), r),
+                    channel(color.g(), g),
+                    channel(color.b(), b),
+                ));
+            }
+            ColorMode::Hsv => {
+                // цвет копится в HSV, чтобы тон не сбивался округлением до RGB
+                let [hue, saturation, value] = self.config.hsv_delta;
+                let hsv = &mut self.state.hsv;
+                hsv.h = (hsv.h + hue / 360.0).rem_euclid(1.0);
+                hsv.s = (hsv.s + saturation).clamp(0.0, 1.0);
+                hsv.v = (hsv.v + value).clamp(0.0, 1.0);
+                self.state.color = egui::Color32::from(*hsv);
+            }
+            ColorMode::GradientIndex | ColorMode::GradientLength => {}
+        }
+    }
+
+    /// Раскрасить линии по градиенту: по номеру линии или по длине пути
+    /// до её середины, от первой линии (начало градиента) до последней (конец).
+    pub(super) fn apply_gradient(&self, lines: &mut [Segment]) {
+        let stops = &self.config.gradient;
+        if !self.config.color_mode.is_gradient() || stops.is_empty() || lines.is_empty() {
+            return;
+        }
+
+        let positions: Vec<f32> = match self.config.color_mode {
+            ColorMode::GradientLength => {
+                let mut path = 0.0;
+                lines
+                    .iter()
+                    .map(|line| {
+                        let length = (line.end - line.begin).length();
+                        path += length;
+                        path - length / 2.0
+                    })
+                    .collect()
+            }
+            _ => (0..lines.len()).map(|i| i as f32).collect(),
+        };
+        // позиции отсчитываются от первой линии, чтобы она получила начало градиента
+        let first = positions[0];
+        let total = positions[positions.len() - 1] - first;
+
+        for (line, position) in lines.iter_mut().zip(positions) {
+            let t = if total > 0.0 {
+                (position - first) / total
+            } else {
+                0.0
+            };
+            line.color = gradient_at(stops, t);
+        }
+    }
+}
+
+/// Точка градиента: позиция от 0 до 1 и цвет в ней.
+pub(super) type GradientStop = (f32, egui::Color32);
+
+/// Цвет градиента в точке t от 0 до 1. До первой точки и после последней цвет не меняется,
+/// а между точками смешивается. Точек должна быть хотя бы одна.
+fn gradient_at(stops: &[GradientStop], t: f32) -> egui::Color32 {
+    // первая точка, которая не левее t
+    let next = stops.partition_point(|&(position, _)| position < t);
+    if next == 0 {
+        return stops[0].1;
+    }
+    let Some(&(to, to_color)) = stops.get(next) else {
+        return stops[stops.len() - 1].1;
+    };
+    let (from, from_color) = stops[next - 1];
+    from_color.lerp_to_gamma(to_color, (t - from) / (to - from))
+}
+
+/// Точки градиента из цветов, расставленных на равных расстояниях.
+pub(super) fn evenly_spaced(colors: Vec<egui::Color32>) -> Vec<GradientStop> {
+    let last = colors.len().saturating_sub(1).max(1) as f32;
+    colors
+        .into_iter()
+        .enumerate()
+        .map(|(i, color)| (i as f32 / last, color))
+        .collect()
+}
+
+/// Стоят ли точки градиента на равных расстояниях (тогда их можно записать одними цветами).
+pub(super) fn is_evenly_spaced(stops: &[GradientStop]) -> bool {
+    let colors = stops.iter().map(|&(_, color)| color).collect();
+    evenly_spaced(colors) == stops
+}
+
+/// Номер первой точки градиента, позиция которой не от 0 до 1 или меньше предыдущей.
+pub(super) fn invalid_gradient_stop(stops: &[GradientStop]) -> Option<usize> {
+    let mut previous = 0.0;
+    stops.iter().position(|&(position, _)| {
+        let invalid = !(previous..=1.0).contains(&position);
+        previous = position;
+        invalid
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use egui::Color32;
+
+    #[test]
+    fn gradient_with_explicit_stops() {
+        let stops = [
+            (0.25, Color32::RED),
+            (0.5, Color32::GREEN),
+            (1.0, Color32::BLUE),
+        ];
+        // до первой точки и после последней цвет не меняется
+        assert_eq!(gradient_at(&stops, 0.0), Color32::RED);
+        assert_eq!(gradient_at(&stops, 0.25), Color32::RED);
+        assert_eq!(gradient_at(&stops, 1.5), Color32::BLUE);
+        // в точке - её цвет, между точками - смесь соседних
+        assert_eq!(gradient_at(&stops, 0.5), Color32::GREEN);
+        assert_eq!(
+            gradient_at(&stops, 0.75),
+            Color32::GREEN.lerp_to_gamma(Color32::BLUE, 0.5)
+        );
+        assert_eq!(
+            gradient_at(&stops, 0.3),
+            Color32::RED.lerp_to_gamma(Color32::GREEN, 0.2)
+        );
+        assert_eq!(gradient_at(&[(0.5, Color32::WHITE)], 0.9), Color32::WHITE);
+    }
+
+    #[test]
+    fn gradient_stops_are_checked() {
+        let even = evenly_spaced(vec![Color32::RED, Color32::GREEN, Color32::BLUE]);
+        assert_eq!(
+            even,
+            [
+                (0.0, Color32::RED),
+                (0.5, Color32::GREEN),
+                (1.0, Color32::BLUE)
+            ]
+        );
+        assert!(is_evenly_spaced(&even));
+        assert!(!is_evenly_spaced(&[
+            (0.0, Color32::RED),
+            (0.3, Color32::GREEN)
+        ]));
+
+        assert_eq!(invalid_gradient_stop(&even), None);
+        let unordered = [(0.5, Color32::RED), (0.2, Color32::GREEN)];
+        assert_eq!(invalid_gradient_stop(&unordered), Some(1));
+        assert_eq!(invalid_gradient_stop(&[(1.5, Color32::RED)]), Some(0));
+    }
+}
@@ -0,0 +1,436 @@
+// --------------------------------------------------
+// Арифметические и логические выражения над параметрами модулей
+// --------------------------------------------------
+
+/// Выражение, вычисляемое над параметрами модуля.
+///
+/// Переменные выражения заранее сопоставляются с позициями параметров,
+/// поэтому при вычислении достаточно передать список значений.
+/// Именованные константы (`DEFINE`) подставляются значениями ещё при разборе.
+#[derive(Debug, Clone)]
+pub struct Expr {
+    /// Исходный текст выражения (для вывода).
+    text: String,
+    /// Дерево выражения.
+    node: Node,
+}
+
+/// Узел дерева выражения.
+#[derive(Debug, Clone)]
+enum Node {
+    /// Числовая константа.
+    Num(f32),
+    /// Параметр с указанным номером.
+    Param(usize),
+    /// Унарная операция.
+    Unary(UnaryOp, Box<Node>),
+    /// Бинарная операция.
+    Binary(BinaryOp, Box<Node>, Box<Node>),
+    /// Вызов встроенной функции.
+    Call(Function, Vec<Node>),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum UnaryOp {
+    Neg,
+    Not,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Pow,
+    Less,
+    LessEq,
+    Greater,
+    GreaterEq,
+    Eq,
+    NotEq,
+    And,
+    Or,
+}
+
+/// Встроенные функции (тригонометрия в градусах, как и углы L-системы).
+#[derive(Debug, Clone, Copy)]
+enum Function {
+    Sqrt,
+    Abs,
+    Sin,
+    Cos,
+    Floor,
+    Min,
+    Max,
+}
+
+impl Function {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "sqrt" => Some(Self::Sqrt),
+            "abs" => Some(Self::Abs),
+            "sin" => Some(Self::Sin),
+            "cos" => Some(Self::Cos),
+            "floor" => Some(Self::Floor),
+            "min" => Some(Self::Min),
+            "max" => Some(Self::Max),
+            _ => None,
+        }
+    }
+
+    /// Количество аргументов функции.
+    fn arity(&self) -> usize {
+        match self {
+            Self::Min | Self::Max => 2,
+            _ => 1,
+        }
+    }
+}
+
+impl Expr {
+    /// Константное выражение.
+    pub fn constant(value: f32) -> Self {
+        Self {
+            text: value.to_string(),
+            node: Node::Num(value),
+        }
+    }
+
+    /// Разобрать выражение, в котором допустимы переменные из params и константы из constants.
+    ///
+    /// Параметр скрывает константу с тем же именем.
+    pub fn parse(
+        text: &str,
+        params: &[String],
+        constants: &[(String, f32)],
+    ) -> Result<Self, String> {
+        let mut parser = ExprParser {
+            chars: text.chars().collect(),
+            pos: 0,
+            params,
+            constants,
+        };
+        let node = parser.parse_or()?;
+        parser.skip_whitespace();
+        if parser.pos < parser.chars.len() {
+            return Err(format!(
+                "лишние символы: '{}'",
+                parser.chars[parser.pos..].iter().collect::<String>()
+            ));
+        }
+        Ok(Self {
+            text: text.trim().into(),
+            node,
+        })
+    }
+
+    /// Значение выражения, если оно не зависит от параметров (`5`, `360/7`).
+    pub fn as_constant(&self) -> Option<f32> {
+        (!self.node.uses_params()).then(|| self.node.eval(&[]))
+    }
+
+    /// Вычислить выражение. Недостающие параметры считаются равными 0.
+    pub fn eval(&self, params: &[f32]) -> f32 {
+        self.node.eval(params)
+    }
+
+    /// Истинно ли выражение (не равно 0).
+    pub fn is_true(&self, params: &[f32]) -> bool {
+        self.eval(params) != 0.0
+    }
+}
+
+impl std::fmt::Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+/// Является ли name именем встроенной функции.
+pub fn is_function_name(name: &str) -> bool {
+    Function::from_name(name).is_some()
+}
+
+impl Node {
+    /// Зависит ли значение узла от параметров.
+    fn uses_params(&self) -> bool {
+        match self {
+            Self::Num(_) => false,
+            Self::Param(_) => true,
+            Self::Unary(_, x) => x.uses_params(),
+            Self::Binary(_, a, b) => a.uses_params() || b.uses_params(),
+            Self::Call(_, args) => args.iter().any(Node::uses_params),
+        }
+    }
+
+    fn eval(&self, params: &[f32]) -> f32 {
+        fn from_bool(value: bool) -> f32 {
+            if value { 1.0 } else { 0.0 }
+        }
+
+        match self {
+            Self::Num(x) => *x,
+            Self::Param(i) => params.get(*i).copied().unwrap_or(0.0),
+            Self::Unary(op, x) => {
+                let x = x.eval(params);
+                match op {
+                    UnaryOp::Neg => -x,
+                    UnaryOp::Not => from_bool(x == 0.0),
+                }
+            }
+            Self::Binary(op, a, b) => {
+                let a = a.eval(params);
+                let b = b.eval(params);
+                match op {
+                    BinaryOp::Add => a + b,
+                    BinaryOp::Sub => a - b,
+                    BinaryOp::Mul => a * b,
+                    BinaryOp::Div => a / b,
+                    BinaryOp::Rem => a % b,
+                    BinaryOp::Pow => a.powf(b),
+                    BinaryOp::Less => from_bool(a < b),
+                    BinaryOp::LessEq => from_bool(a <= b),
+                    BinaryOp::Greater => from_bool(a > b),
+                    BinaryOp::GreaterEq => from_bool(a >= b),
+                    BinaryOp::Eq => from_bool(a == b),
+                    BinaryOp::NotEq => from_bool(a != b),
+                    BinaryOp::And => from_bool(a != 0.0 && b != 0.0),
+                    BinaryOp::Or => from_bool(a != 0.0 || b != 0.0),
+                }
+            }
+            Self::Call(function, args) => {
+                let x = args[0].eval(params);
+                match function {
+                    Function::Sqrt => x.sqrt(),
+                    Function::Abs => x.abs(),
+                    Function::Sin => x.to_radians().sin(),
+                    Function::Cos => x.to_radians().cos(),
+                    Function::Floor => x.floor(),
+                    Function::Min => x.min(args[1].eval(params)),
+                    Function::Max => x.max(args[1].eval(params)),
+                }
+            }
+        }
+    }
+}
+
+// --------------------------------------------------
+// Разбор выражений (рекурсивный спуск)
+// --------------------------------------------------
+
+/*
+
+<выражение> ::= <и> { "||" <и> }
+<и> ::= <сравнение> { "&&" <сравнение> }
+<сравнение> ::= <сумма> [ ("<" | "<=" | ">" | ">=" | "==" | "!=") <сумма> ]
+<сумма> ::= <произведение> { ("+" | "-") <произведение> }
+<произведение> ::= <унарное> { ("*" | "/" | "%") <унарное> }
+<унарное> ::= ("-" | "+" | "!") <унарное> | <степень>
+<степень> ::= <атом> [ "^" <унарное> ]
+<атом> ::= <число> | <переменная> | <константа> | <функция> "(" <выражение> { "," <выражение> } ")"
+    | "(" <выражение> ")"
+*/
+
+struct ExprParser<'a> {
+    chars: Vec<char>,
+    pos: usize,
+    params: &'a [String],
+    constants: &'a [(String, f32)],
+}
+
+impl ExprParser<'_> {
+    fn skip_whitespace(&mut self) {
+        while self.pos < self.chars.len() && self.chars[self.pos].is_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    /// Пропустить пробелы и попытаться прочитать указанный оператор.
+    fn eat(&mut self, op: &str) -> bool {
+        self.skip_whitespace();
+        let op: Vec<char> = op.chars().collect();
+        if self.chars[self.pos..].starts_with(&op) {
+            self.pos += op.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Node, String> {
+        let mut node = self.parse_and()?;
+        while self.eat("||") {
+            node = Node::Binary(BinaryOp::Or, Box::new(node), Box::new(self.parse_and()?));
+        }
+        Ok(node)
+    }
+
+    fn parse_and(&mut self) -> Result<Node, String> {
+        let mut node = self.parse_comparison()?;
+        while self.eat("&&") {
+            node = Node::Binary(
+                BinaryOp::And,
+                Box::new(node),
+                Box::new(self.parse_comparison()?),
+            );
+        }
+        Ok(node)
+    }
+
+    fn parse_comparison(&mut self) -> Result<Node, String> {
+        let node = self.parse_sum()?;
+        // двухсимвольные операторы проверяются раньше односимвольных
+        let ops = [
+            ("<=", BinaryOp::LessEq),
+            (">=", BinaryOp::GreaterEq),
+            ("==", BinaryOp::Eq),
+            ("!=", BinaryOp::NotEq),
+            ("<", BinaryOp::Less),
+            (">", BinaryOp::Greater),
+        ];
+        for (text, op) in ops {
+            if self.eat(text) {
+                return Ok(Node::Binary(
+                    op,
+                    Box::new(node),
+                    Box::new(self.parse_sum()?),
+                ));
+            }
+        }
+        Ok(node)
+    }
+
+    fn parse_sum(&mut self) -> Result<Node, String> {
+        let mut node = self.parse_product()?;
+        loop {
+            let op = if self.eat("+") {
+                BinaryOp::Add
+            } else if self.eat("-") {
+                BinaryOp::Sub
+            } else {
+                return Ok(node);
+            };
+            node = Node::Binary(op, Box::new(node), Box::new(self.parse_product()?));
+        }
+    }
+
+    fn parse_product(&mut self) -> Result<Node, String> {
+        let mut node = self.parse_unary()?;
+        loop {
+            let op = if self.eat("*") {
+                BinaryOp::Mul
+            } else if self.eat("/") {
+                BinaryOp::Div
+            } else if self.eat("%") {
+                BinaryOp::Rem
+            } else {
+                return Ok(node);
+            };
+            node = Node::Binary(op, Box::new(node), Box::new(self.parse_unary()?));
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Node, String> {
+        if self.eat("-") {
+            Ok(Node::Unary(UnaryOp::Neg, Box::new(self.parse_unary()?)))
+        } else if self.eat("+") {
+            self.parse_unary()
+        } else if self.eat("!") {
+            Ok(Node::Unary(UnaryOp::Not, Box::new(self.parse_unary()?)))
+        } else {
+            self.parse_power()
+        }
+    }
+
+    fn parse_power(&mut self) -> Result<Node, String> {
+        let node = self.parse_atom()?;
+        if self.eat("^") {
+            return Ok(Node::Binary(
+                BinaryOp::Pow,
+                Box::new(node),
+                Box::new(self.parse_unary()?),
+            ));
+        }
+        Ok(node)
+    }
+
+    fn parse_atom(&mut self) -> Result<Node, String> {
+        self.skip_whitespace();
+        let Some(&ch) = self.chars.get(self.pos) else {
+            return Err("выражение оборвалось".into());
+        };
+
+        // ( <выражение> )
+        if ch == '(' {
+            self.pos += 1;
+            let node = self.parse_or()?;
+            if !self.eat(")") {
+                return Err("ожидалась ')'".into());
+            }
+            return Ok(node);
+        }
+
+        // <число>
+        if ch.is_ascii_digit() || ch == '.' {
+            let start = self.pos;
+            while self.pos < self.chars.len()
+                && (self.chars[self.pos].is_ascii_digit() || self.chars[self.pos] == '.')
+            {
+                self.pos += 1;
+            }
+            let text: String = self.chars[start..self.pos].iter().collect();
+            return text
+                .parse()
+                .map(Node::Num)
+                .map_err(|_| format!("некорректное число '{}'", text));
+        }
+
+        // <переменная> | <функция>(...)
+        if ch.is_alphabetic() || ch == '_' {
+            let start = self.pos;
+            while self.pos < self.chars.len()
+                && (self.chars[self.pos].is_alphanumeric() || self.chars[self.pos] == '_')
+            {
+                self.pos += 1;
+            }
+            let name: String = self.chars[start..self.pos].iter().collect();
+
+            if let Some(i) = self.params.iter().position(|p| *p == name) {
+                return Ok(Node::Param(i));
+            }
+            if let Some((_, value)) = self.constants.iter().find(|(c, _)| *c == name) {
+                return Ok(Node::Num(*value));
+            }
+            if let Some(function) = Function::from_name(&name) {
+                return self.parse_call(function, &name);
+            }
+            return Err(format!("неизвестная переменная '{}'", name));
+        }
+
+        Err(format!("неожиданный символ '{}'", ch))
+    }
+
+    fn parse_call(&mut self, function: Function, name: &str) -> Result<Node, String> {
+        if !self.eat("(") {
+            return Err(format!("ожидалась '(' после '{}'", name));
+        }
+        let mut args = vec![self.parse_or()?];
+        while self.eat(",") {
+            args.push(self.parse_or()?);
+        }
+        if !self.eat(")") {
+            return Err(format!("ожидалась ')' после аргументов '{}'", name));
+        }
+        if args.len() != function.arity() {
+            return Err(format!(
+                "'{}' ожидает {} аргумент(а), получено {}",
+                name,
+                function.arity(),
+                args.len()
+            ));
+        }
+        Ok(Node::Call(function, args))
+    }
+}
@@ -0,0 +1,421 @@
+// --------------------------------------------------
+// Импорт библиотек L-систем в формате Fractint (.l)
+// --------------------------------------------------
+
+use super::expr::Expr;
+use super::lexer::{Pos, Span};
+use super::parser::{self, LParseErr, LParseErrors};
+use super::{
+    ActionDef, Actions, LSystemConfig, Module, ModulePattern, ModuleTemplate, Production, Symbol,
+};
+use std::collections::HashSet;
+use std::path::Path;
+
+/*
+
+Файл Fractint содержит несколько именованных L-систем:
+```
+; комментарий до конца строки
+Koch1 {          ; имя и открывающая скобка
+  Angle 6        ; угол поворота - 360 / 6 градусов
+  Axiom F--F--F
+  F=F+F--F+F     ; правило <символ>=<строка>
+}
+```
+Регистр букв не важен, пробелы внутри аксиомы и правил пропускаются.
+
+Команды переводятся в действия так:
+F, D         - FORWARD 1
+G, M         - MOVE 1
++ и -        - поворот на угол из Angle (во Fractint `+` поворачивает против часовой стрелки)
+\<n> и /<n>  - поворот на n градусов (как `+` и `-` соответственно)
+|            - TURN_AROUND
+!            - ROLL 180: меняет местами смысл `+` и `-` (и `\`, `/`)
+[ и ]        - SAVE и RESTORE
+@<n>         - SCALE_LENGTH n, перед числом можно указать I (1/n) и Q (квадратный корень),
+               например `@IQ2` - это 1/sqrt(2)
+C<n>         - SET_COLOR n
+<<n> и ><n>  - n раз NEXT_COLOR и PREV_COLOR (без числа - один раз)
+Остальные символы ничего не рисуют. Чертёжник начинает движение вправо.
+
+*/
+
+/// L-система из библиотеки Fractint.
+#[derive(Debug, Clone)]
+pub struct FractintEntry {
+    /// Имя L-системы.
+    pub name: String,
+    /// Фрагмент файла с именем.
+    span: Span,
+    /// Количество делений полного круга (угол поворота - 360 / angle).
+    angle: Option<f32>,
+    /// Аксиома и фрагмент файла с ней.
+    axiom: (String, Span),
+    /// Правила: символ, строка, на которую он заменяется, и фрагмент файла с правилом.
+    rules: Vec<(char, String, Span)>,
+}
+
+/// Модуль Fractint: символ и, для команд с числом, его значение.
+type FractintModule = (char, Option<f32>);
+
+/// 16 цветов EGA - начало стандартной палитры Fractint.
+const EGA_PALETTE: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (0, 0, 170),
+    (0, 170, 0),
+    (0, 170, 170),
+    (170, 0, 0),
+    (170, 0, 170),
+    (170, 85, 0),
+    (170, 170, 170),
+    (85, 85, 85),
+    (85, 85, 255),
+    (85, 255, 85),
+    (85, 255, 255),
+    (255, 85, 85),
+    (255, 85, 255),
+    (255, 255, 85),
+    (255, 255, 255),
+];
+
+/// Прочитать библиотеку L-систем в формате Fractint.
+pub fn read_fractint_library(
+    file_path: impl AsRef<Path>,
+) -> Result<Vec<FractintEntry>, Box<dyn std::error::Error>> {
+    let text = std::fs::read_to_string(file_path)?;
+    parse_fractint_library(&text)
+}
+
+/// Разобрать текст библиотеки L-систем в формате Fractint.
+///
+/// Ошибка не останавливает разбор: в случае ошибок возвращается LParseErrors со всеми ними.
+pub fn parse_fractint_library(
+    text: &str,
+) -> Result<Vec<FractintEntry>, Box<dyn std::error::Error>> {
+    let mut entries = Vec::new();
+    let mut errors = Vec::new();
+    let mut current: Option<FractintEntry> = None;
+
+    for (i, line) in text.lines().enumerate() {
+        let mut code = line.split(';').next().unwrap_or("");
+        // фрагмент строки без пробелов по краям (code - срез line)
+        let span = |code: &str| {
+            let code = code.trim();
+            let offset = code.as_ptr() as usize - line.as_ptr() as usize;
+            let start = Pos {
+                line: i + 1,
+                col: line[..offset].chars().count() + 1,
+            };
+            Span::new(start, code.chars().count())
+        };
+
+        // <имя> {
+        if current.is_none() {
+            if code.trim().is_empty() {
+                continue;
+            }
+            let Some((name, rest)) = code.split_once('{') else {
+                errors.push(LParseErr::UnexpectedValue(
+                    span(code),
+                    "<имя> {".into(),
+                    code.trim().into(),
+                ));
+                continue;
+            };
+            current = Some(FractintEntry {
+                name: name.trim().to_string(),
+                span: span(name),
+                angle: None,
+                axiom: (String::new(), span(name)),
+                rules: Vec::new(),
+            });
+            code = rest;
+        }
+
+        // <команда> [}]
+        let (statement, closed) = match code.split_once('}') {
+            Some((statement, _)) => (statement, true),
+            None => (code, false),
+        };
+        if let Some(entry) = &mut current
+            && let Err(err) = parse_statement(entry, statement, span(statement))
+        {
+            errors.push(err);
+        }
+        if closed && let Some(entry) = current.take() {
+            entries.push(entry);
+        }
+    }
+
+    if let Some(entry) = current {
+        errors.push(LParseErr::UnexpectedValue(
+            entry.span,
+            "}".into(),
+            format!("конец файла в '{}'", entry.name),
+        ));
+    }
+    if !errors.is_empty() {
+        return Err(Box::new(LParseErrors(errors)));
+    }
+    Ok(entries)
+}
+
+/// Разобрать строку внутри L-системы: `Angle <n>`, `Axiom <строка>` или `<символ>=<строка>`.
+fn parse_statement(
+    entry: &mut FractintEntry,
+    statement: &str,
+    span: Span,
+) -> Result<(), LParseErr> {
+    let statement = statement.trim();
+    if statement.is_empty() {
+        return Ok(());
+    }
+    let (keyword, rest) = statement
+        .split_once(char::is_whitespace)
+        .unwrap_or((statement, ""));
+
+    if keyword.eq_ignore_ascii_case("angle") {
+        let angle: f32 = rest.trim().parse().map_err(|_| {
+            LParseErr::UnexpectedValue(span, "<количество делений круга>".into(), rest.into())
+        })?;
+        if angle == 0.0 || !angle.is_finite() {
+            return Err(LParseErr::UnexpectedValue(
+                span,
+                "<ненулевое количество делений круга>".into(),
+                rest.into(),
+            ));
+        }
+        entry.angle = Some(angle);
+    } else if keyword.eq_ignore_ascii_case("axiom") {
+        entry.axiom = (normalize(rest), span);
+    } else if let Some((symbol, successor)) = statement.split_once('=') {
+        let symbol = normalize(symbol);
+        let mut chars = symbol.chars();
+        let (Some(symbol), None) = (chars.next(), chars.next()) else {
+            return Err(LParseErr::UnexpectedValue(
+                span,
+                "<символ>=<строка>".into(),
+                statement.into(),
+            ));
+        };
+        // во Fractint действует последнее правило символа
+        entry.rules.retain(|(known, _, _)| *known != symbol);
+        entry.rules.push((symbol, normalize(successor), span));
+    } else {
+        return Err(LParseErr::UnexpectedValue(
+            span,
+            "Angle | Axiom | <символ>=<строка>".into(),
+            statement.into(),
+        ));
+    }
+    Ok(())
+}
+
+/// Привести строку к виду, в котором её понимает Fractint: без пробелов, в верхнем регистре.
+fn normalize(text: &str) -> String {
+    text.chars()
+        .filter(|ch| !ch.is_whitespace())
+        .flat_map(char::to_uppercase)
+        .collect()
+}
+
+impl FractintEntry {
+    /// Перевести L-систему Fractint в конфигурацию L-системы.
+    pub fn to_config(&self) -> Result<LSystemConfig, Box<dyn std::error::Error>> {
+        let Some(divisions) = self.angle else {
+            return Err(Box::new(LParseErr::UnexpectedValue(
+                self.span,
+                "Angle <количество делений круга>".into(),
+                format!("'{}' без Angle", self.name),
+            )));
+        };
+        let rotate_angle = 360.0 / divisions;
+
+        let (axiom, axiom_span) = &self.axiom;
+        let axiom = split_commands(axiom, *axiom_span)?;
+        let mut used: HashSet<char> = axiom.iter().map(|&(ch, _)| ch).collect();
+        let axiom = axiom
+            .into_iter()
+            .map(|(ch, value)| Module {
+                symbol: Symbol::Char(ch),
+                params: value.into_iter().collect(),
+            })
+            .collect();
+        let mut config = parser::empty_config(axiom, rotate_angle, 0.0);
+
+        for (symbol, successor, span) in &self.rules {
+            let successor = split_commands(successor, *span)?;
+            used.extend(successor.iter().map(|&(ch, _)| ch));
+            let successor = successor
+                .into_iter()
+                .map(|(ch, value)| ModuleTemplate {
+                    symbol: Symbol::Char(ch),
+                    args: value.into_iter().map(Expr::constant).collect(),
+                })
+                .collect();
+            config.rules.insert(
+                Symbol::Char(*symbol),
+                vec![Production {
+                    left_context: Vec::new(),
+                    predecessor: ModulePattern {
+                        symbol: Symbol::Char(*symbol),
+                        params: Vec::new(),
+                    },
+                    right_context: Vec::new(),
+                    condition: None,
+                    successor,
+                    weight: 1.0,
+                }],
+            );
+        }
+
+        for ch in used {
+            if let Some(action) = command_action(ch, rotate_angle) {
+                config.actions.insert(Symbol::Char(ch), action);
+            }
+        }
+        if config.actions.values().any(|def| {
+            matches!(
+                def.action,
+                Actions::NextColor | Actions::PrevColor | Actions::SetColor(_)
+            )
+        }) {
+            config.palette = EGA_PALETTE
+                .iter()
+                .map(|&(r, g, b)| egui::Color32::from_rgb(r, g, b))
+                .collect();
+        }
+        Ok(config)
+    }
+}
+
+/// Разбить строку Fractint на модули: у `\ / @ C` число становится параметром модуля,
+/// а `<n` и `>n` разворачиваются в n одинаковых модулей.
+/// Ошибки в числах указывают на фрагмент span - строку файла с text.
+fn split_commands(
+    text: &str,
+    span: Span,
+) -> Result<Vec<FractintModule>, Box<dyn std::error::Error>> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut modules = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let ch = chars[i];
+        i += 1;
+        if !matches!(ch, '\\' | '/' | '@' | 'C' | '<' | '>') {
+            modules.push((ch, None));
+            continue;
+        }
+
+        // @ допускает префиксы I (обратное число) и Q (квадратный корень)
+        let prefixes_start = i;
+        if ch == '@' {
+            while i < chars.len() && matches!(chars[i], 'I' | 'Q') {
+                i += 1;
+            }
+        }
+        let prefixes = &chars[prefixes_start..i];
+        let number_start = i;
+        while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+            i += 1;
+        }
+        let number: String = chars[number_start..i].iter().collect();
+
+        if matches!(ch, '<' | '>') {
+            let count = if number.is_empty() {
+                1
+            } else {
+                parse_number::<usize>(&number, text, span)?
+            };
+            modules.extend(std::iter::repeat_n((ch, None), count));
+            continue;
+        }
+        let mut value: f32 = parse_number(&number, text, span)?;
+        for prefix in prefixes.iter().rev() {
+            value = match prefix {
+                'I' => 1.0 / value,
+                _ => value.sqrt(),
+            };
+        }
+        modules.push((ch, Some(value)));
+    }
+    Ok(modules)
+}
+
+/// Разобрать число после команды в строке text (span - фрагмент файла с этой строкой).
+fn parse_number<T: std::str::FromStr>(
+    number: &str,
+    text: &str,
+    span: Span,
+) -> Result<T, Box<dyn std::error::Error>> {
+    number.parse().map_err(|_| {
+        LParseErr::UnexpectedValue(
+            span,
+            "<число после команды>".into(),
+            format!("'{}' в '{}'", number, text),
+        )
+        .into()
+    })
+}
+
+/// Действие для команды Fractint (None для символов, которые ничего не делают).
+fn command_action(ch: char, rotate_angle: f32) -> Option<ActionDef> {
+    let param = || vec!["n".to_string()];
+    let expr = |text: &str| Expr::parse(text, &param(), &[]).expect("Выражение команды Fractint");
+    let (params, action) = match ch {
+        'F' | 'D' => (Vec::new(), Actions::Forward(Expr::constant(1.0))),
+        'G' | 'M' => (Vec::new(), Actions::Move(Expr::constant(1.0))),
+        // y на холсте направлен вниз, поэтому поворот против часовой стрелки - отрицательный
+        '+' => (Vec::new(), Actions::Rotate(Expr::constant(-rotate_angle))),
+        '-' => (Vec::new(), Actions::Rotate(Expr::constant(rotate_angle))),
+        '\\' => (param(), Actions::Rotate(expr("-n"))),
+        '/' => (param(), Actions::Rotate(expr("n"))),
+        '|' => (Vec::new(), Actions::TurnAround),
+        '!' => (Vec::new(), Actions::Roll(Expr::constant(180.0))),
+        '[' => (Vec::new(), Actions::Save),
+        ']' => (Vec::new(), Actions::Restore),
+        '@' => (param(), Actions::ScaleLength(expr("n"))),
+        'C' => (param(), Actions::SetColor(expr("n"))),
+        '<' => (Vec::new(), Actions::NextColor),
+        '>' => (Vec::new(), Actions::PrevColor),
+        _ => return None,
+    };
+    Some(ActionDef { params, action })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands_take_numbers_and_prefixes() {
+        let span = Span::default();
+        let modules = split_commands("F\\30@IQ4C2<2+", span).unwrap();
+        assert_eq!(
+            modules,
+            vec![
+                ('F', None),
+                ('\\', Some(30.0)),
+                ('@', Some(0.5)),
+                ('C', Some(2.0)),
+                ('<', None),
+                ('<', None),
+                ('+', None),
+            ]
+        );
+    }
+
+    #[test]
+    fn bad_number_is_reported_at_its_line() {
+        let entries =
+            parse_fractint_library("Test {\n  Angle 6\n  Axiom F\n  F=F+F@Q.5.5\n}").unwrap();
+        let err = entries[0].to_config().err().unwrap();
+        let err = err.downcast_ref::<LParseErr>().unwrap();
+        assert_eq!(err.span().start, Pos { line: 4, col: 3 });
+
+        let entries = parse_fractint_library("Test {\n  Angle 6\n  Axiom F\\x\n}").unwrap();
+        let err = entries[0].to_config().err().unwrap();
+        let err = err.downcast_ref::<LParseErr>().unwrap();
+        assert_eq!(err.span().start.line, 3);
+    }
+}
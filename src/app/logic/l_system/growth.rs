@@ -0,0 +1,164 @@
+// --------------------------------------------------
+// Прогноз роста L-системы по векторам Парикха
+// --------------------------------------------------
+
+use super::{Actions, LSystemConfig, Lsystem, Module, Production, Segment, Symbol};
+use crate::app::logic::utils;
+use std::collections::HashMap;
+
+/// Прогноз размера L-системы на некоторой итерации.
+#[derive(Debug, Clone, Copy)]
+pub struct Growth {
+    /// Номер итерации.
+    pub iteration: usize,
+    /// Ожидаемая длина строки (количество модулей).
+    pub modules: f64,
+    /// Ожидаемое количество нарисованных отрезков.
+    pub segments: f64,
+    /// Ожидаемый объём памяти под строку и изображение (в байтах).
+    pub bytes: f64,
+}
+
+impl Growth {
+    /// Ожидаемый объём памяти в мегабайтах.
+    pub fn megabytes(&self) -> f64 {
+        self.bytes / (1024.0 * 1024.0)
+    }
+}
+
+/// Вектор Парикха: сколько раз каждый символ встречается в строке.
+type Parikh = HashMap<Symbol, f64>;
+
+impl LSystemConfig {
+    /// Матрица продукций: сколько в среднем символов каждого вида получается из символа
+    /// по общим правилам и правилам таблицы table.
+    ///
+    /// Продукции символа усредняются с учётом весов. Контекст и условия не учитываются,
+    /// так что для контекстных и параметрических L-систем это только оценка.
+    fn production_matrix(&self, table: Option<usize>) -> HashMap<Symbol, Parikh> {
+        let mut rules: HashMap<&Symbol, &Vec<Production>> = self.rules.iter().collect();
+        if let Some(table) = table {
+            rules.extend(&self.tables[table].rules);
+        }
+
+        let mut matrix = HashMap::new();
+        for (symbol, productions) in rules {
+            let total: f32 = productions.iter().map(|p| p.weight).sum();
+            let mut row = Parikh::new();
+            for production in productions {
+                let share = (production.weight / total) as f64;
+                for template in &production.successor {
+                    *row.entry(template.symbol.clone()).or_default() += share;
+                }
+            }
+            matrix.insert(symbol.clone(), row);
+        }
+        matrix
+    }
+
+    /// Матрицы продукций: общая (с номером 0) и для каждой таблицы правил (с номером таблицы + 1).
+    fn production_matrices(&self) -> Vec<HashMap<Symbol, Parikh>> {
+        std::iter::once(None)
+            .chain((0..self.tables.len()).map(Some))
+            .map(|table| self.production_matrix(table))
+            .collect()
+    }
+
+    /// Матрица продукций для перехода с уровня level на следующий.
+    fn matrix_at<'a>(
+        &self,
+        matrices: &'a [HashMap<Symbol, Parikh>],
+        level: usize,
+    ) -> &'a HashMap<Symbol, Parikh> {
+        &matrices[self.schedule.table_at(level).map_or(0, |table| table + 1)]
+    }
+
+    /// Вектор Парикха строки модулей.
+    fn parikh(modules: &[Module]) -> Parikh {
+        let mut vector = Parikh::new();
+        for module in modules {
+            *vector.entry(module.symbol.clone()).or_default() += 1.0;
+        }
+        vector
+    }
+
+    /// Вектор Парикха следующей итерации.
+    fn next_parikh(matrix: &HashMap<Symbol, Parikh>, vector: &Parikh) -> Parikh {
+        let mut next = Parikh::new();
+        for (symbol, count) in vector {
+            match matrix.get(symbol) {
+                Some(row) => {
+                    for (successor, k) in row {
+                        *next.entry(successor.clone()).or_default() += count * k;
+                    }
+                }
+                // символы без продукций переписываются сами в себя
+                None => *next.entry(symbol.clone()).or_default() += count,
+            }
+        }
+        next
+    }
+
+    /// Сколько в строке символов, рисующих отрезок.
+    fn count_segments(&self, vector: &Parikh) -> f64 {
+        vector
+            .iter()
+            .filter(|(symbol, _)| {
+                matches!(
+                    self.actions.get(symbol).map(|a| &a.action),
+                    Some(Actions::Forward(_) | Actions::ForwardRandom(..))
+                )
+            })
+            .map(|(_, count)| count)
+            .sum()
+    }
+}
+
+impl Lsystem {
+    /// Вектор Парикха строки на итерации iteration (первая итерация - аксиома).
+    fn parikh_at(&self, matrices: &[HashMap<Symbol, Parikh>], iteration: usize) -> Parikh {
+        let mut vector = LSystemConfig::parikh(&self.config.axiom);
+        for level in 0..iteration.saturating_sub(1) {
+            let matrix = self.config.matrix_at(matrices, level);
+            vector = LSystemConfig::next_parikh(matrix, &vector);
+        }
+        vector
+    }
+
+    /// Ожидаемая длина строки на итерации iteration.
+    pub(super) fn expected_modules(&self, iteration: usize) -> f64 {
+        let matrices = self.config.production_matrices();
+        self.parikh_at(&matrices, iteration).values().sum()
+    }
+
+    /// Спрогнозировать размер L-системы на следующих count итерациях.
+    pub fn predict_growth(&self, count: usize) -> Vec<Growth> {
+        let matrices = self.config.production_matrices();
+        let mut vector = self.parikh_at(&matrices, self.iter);
+
+        // отрезок хранится в пространстве и ещё раз проецируется при каждом рисовании
+        let segment_bytes = (size_of::<Segment>() + size_of::<utils::Line>()) as f64;
+        // строку целиком хранят только контекстные L-системы
+        let module_bytes = if self.cur_string.is_some() {
+            size_of::<Module>() as f64
+        } else {
+            0.0
+        };
+
+        (1..=count)
+            .map(|i| {
+                // переход на итерацию self.iter + i идёт с уровня self.iter + i - 2
+                let matrix = self.config.matrix_at(&matrices, self.iter + i - 2);
+                vector = LSystemConfig::next_parikh(matrix, &vector);
+                let modules = vector.values().sum();
+                let segments = self.config.count_segments(&vector);
+                Growth {
+                    iteration: self.iter + i,
+                    modules,
+                    segments,
+                    bytes: modules * module_bytes + segments * segment_bytes,
+                }
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,314 @@
+// --------------------------------------------------
+// Разбиение файла конфигурации L-системы на лексемы
+// --------------------------------------------------
+
+/// Позиция в файле конфигурации (строки и столбцы нумеруются с единицы).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Pos {
+    /// Номер строки.
+    pub line: usize,
+    /// Номер столбца (в символах, а не байтах).
+    pub col: usize,
+}
+
+impl std::fmt::Display for Pos {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "строка {}, столбец {}", self.line, self.col)
+    }
+}
+
+/// Фрагмент строки файла конфигурации: от позиции start до позиции end (не включая её).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Span {
+    /// Начало фрагмента.
+    pub start: Pos,
+    /// Конец фрагмента (в той же строке).
+    pub end: Pos,
+}
+
+impl Span {
+    /// Фрагмент из len символов, начинающийся в позиции start.
+    pub fn new(start: Pos, len: usize) -> Self {
+        Self {
+            start,
+            end: Pos {
+                line: start.line,
+                col: start.col + len,
+            },
+        }
+    }
+}
+
+/// Вид лексемы.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum TokenKind {
+    /// Слово: модули, ключевые слова, числа, выражения.
+    Word,
+    /// Стрелка продукции `->` или стрелка с весом `-(<вес>)->`.
+    Arrow,
+    /// `=` присвоения.
+    Assign,
+    /// `<` после левого контекста.
+    LeftContext,
+    /// `>` перед правым контекстом.
+    RightContext,
+    /// `:` перед условием продукции.
+    Colon,
+}
+
+/// Лексема строки конфигурации.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Token<'a> {
+    /// Вид лексемы.
+    pub kind: TokenKind,
+    /// Текст лексемы.
+    pub text: &'a str,
+    /// Смещение текста лексемы в строке (в байтах).
+    pub offset: usize,
+    /// Позиция начала лексемы.
+    pub pos: Pos,
+}
+
+impl<'a> Token<'a> {
+    /// Фрагмент строки, занятый лексемой.
+    pub fn span(&self) -> Span {
+        Span::new(self.pos, self.text.chars().count())
+    }
+
+    /// Вес стрелки `-(<вес>)->` (для остальных лексем - None).
+    pub fn weight(&self) -> Option<&'a str> {
+        if self.kind != TokenKind::Arrow {
+            return None;
+        }
+        self.text
+            .strip_prefix("-(")
+            .and_then(|text| text.strip_suffix(")->"))
+    }
+}
+
+/// Непустая строка конфигурации, разбитая на лексемы.
+#[derive(Debug)]
+pub(super) struct Line<'a> {
+    /// Номер строки.
+    pub number: usize,
+    /// Текст строки без комментария.
+    pub code: &'a str,
+    /// Лексемы строки.
+    pub tokens: Vec<Token<'a>>,
+}
+
+impl<'a> Line<'a> {
+    /// Текст строки от начала лексемы from до конца лексемы to (не включая её).
+    pub fn slice(&self, from: usize, to: usize) -> &'a str {
+        if from >= to {
+            return "";
+        }
+        let end = &self.tokens[to - 1];
+        &self.code[self.tokens[from].offset..end.offset + end.text.len()]
+    }
+
+    /// Текст строки от начала лексемы from до конца строки.
+    pub fn rest(&self, from: usize) -> &'a str {
+        self.slice(from, self.tokens.len())
+    }
+
+    /// Фрагмент строки от начала лексемы from до конца лексемы to (не включая её).
+    ///
+    /// Если лексем в диапазоне нет, фрагмент пустой и стоит в позиции лексемы from.
+    pub fn span(&self, from: usize, to: usize) -> Span {
+        Span::new(self.pos(from), self.slice(from, to).chars().count())
+    }
+
+    /// Фрагмент строки от начала лексемы from до конца строки.
+    pub fn span_rest(&self, from: usize) -> Span {
+        self.span(from, self.tokens.len())
+    }
+
+    /// Позиция лексемы index (или конца строки, если лексем меньше).
+    pub fn pos(&self, index: usize) -> Pos {
+        match self.tokens.get(index) {
+            Some(token) => token.pos,
+            None => Pos {
+                line: self.number,
+                col: self.code.trim_end().chars().count() + 1,
+            },
+        }
+    }
+}
+
+/// Разбить текст конфигурации на строки с лексемами.
+///
+/// Пустые строки и строки из одних комментариев пропускаются.
+pub(super) fn tokenize(text: &str) -> Vec<Line<'_>> {
+    text.lines()
+        .enumerate()
+        .map(|(i, line)| tokenize_line(i + 1, line))
+        .filter(|line| !line.tokens.is_empty())
+        .collect()
+}
+
+/// Разбить одну строку на лексемы.
+///
+/// Слова разделяются пробелами, но внутри круглых скобок пробелы слово не разрывают:
+/// `F(1, 2)` - одно слово. Комментарий начинается с `#` и длится до конца строки.
+/// `->` (и `-(<вес>)->`) выделяется всегда, даже без пробелов вокруг.
+/// Остальные разделители зависят от места в строке (до стрелки и присвоения):
+/// - `=` - только одиночный (не часть `==`, `<=`, `>=`, `!=`) и не в начале строки;
+/// - `<` и `>` - только отдельным словом, не в начале строки и не в условии;
+/// - `:` - не в начале строки.
+///
+/// В начале строки все эти символы - обычные символы модулей (например, `< -> ROTATE +`).
+fn tokenize_line<'a>(number: usize, line: &'a str) -> Line<'a> {
+    let code = match line.find('#') {
+        Some(end) => &line[..end],
+        None => line,
+    };
+    let chars: Vec<(usize, char)> = code.char_indices().collect();
+    let char_at = |k: usize| chars.get(k).map(|&(_, ch)| ch);
+    let offset_at = |k: usize| chars.get(k).map_or(code.len(), |&(i, _)| i);
+
+    let mut tokens: Vec<Token<'a>> = Vec::new();
+    // начало текущего слова (индекс символа)
+    let mut word_start: Option<usize> = None;
+    let mut depth = 0;
+    // встретились ли уже стрелка или присвоение: после них идёт правая часть
+    let mut right_side = false;
+    let mut in_condition = false;
+    let mut has_left_context = false;
+    let mut has_right_context = false;
+
+    let push = |tokens: &mut Vec<Token<'a>>, kind, from: usize, to: usize| {
+        tokens.push(Token {
+            kind,
+            text: &code[offset_at(from)..offset_at(to)],
+            offset: offset_at(from),
+            pos: Pos {
+                line: number,
+                col: from + 1,
+            },
+        });
+    };
+    let end_word = |tokens: &mut Vec<Token<'a>>, word_start: &mut Option<usize>, end: usize| {
+        if let Some(start) = word_start.take() {
+            push(tokens, TokenKind::Word, start, end);
+        }
+    };
+
+    let mut k = 0;
+    while k < chars.len() {
+        let ch = chars[k].1;
+        let at_line_start = tokens.is_empty() && word_start.is_none_or(|start| start == k);
+
+        if depth > 0 {
+            match ch {
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                _ => {}
+            }
+            k += 1;
+            continue;
+        }
+
+        // -> и -(<вес>)->
+        if ch == '-' && char_at(k + 1) == Some('>') {
+            end_word(&mut tokens, &mut word_start, k);
+            push(&mut tokens, TokenKind::Arrow, k, k + 2);
+            right_side = true;
+            k += 2;
+            continue;
+        }
+        if ch == '-' && char_at(k + 1) == Some('(') && !right_side && !at_line_start {
+            let close = matching_paren(&chars, k + 1);
+            if let Some(close) = close
+                && char_at(close + 1) == Some('-')
+                && char_at(close + 2) == Some('>')
+            {
+                end_word(&mut tokens, &mut word_start, k);
+                push(&mut tokens, TokenKind::Arrow, k, close + 3);
+                right_side = true;
+                k = close + 3;
+                continue;
+            }
+        }
+
+        let separated = |k: usize| {
+            (k == 0 || char_at(k - 1).is_some_and(char::is_whitespace))
+                && char_at(k + 1).is_none_or(char::is_whitespace)
+        };
+        let kind = match ch {
+            '=' if !right_side
+                && !in_condition
+                && !at_line_start
+                && !matches!(char_at(k + 1), Some('=' | '<' | '>' | '!'))
+                && !matches!(
+                    k.checked_sub(1).and_then(char_at),
+                    Some('=' | '<' | '>' | '!')
+                ) =>
+            {
+                right_side = true;
+                Some(TokenKind::Assign)
+            }
+            '<' if !right_side
+                && !in_condition
+                && !at_line_start
+                && !has_left_context
+                && !has_right_context
+                && separated(k) =>
+            {
+                has_left_context = true;
+                Some(TokenKind::LeftContext)
+            }
+            '>' if !right_side
+                && !in_condition
+                && !at_line_start
+                && !has_right_context
+                && separated(k) =>
+            {
+                has_right_context = true;
+                Some(TokenKind::RightContext)
+            }
+            ':' if !right_side && !in_condition && !at_line_start => {
+                in_condition = true;
+                Some(TokenKind::Colon)
+            }
+            _ => None,
+        };
+        if let Some(kind) = kind {
+            end_word(&mut tokens, &mut word_start, k);
+            push(&mut tokens, kind, k, k + 1);
+        } else if ch.is_whitespace() {
+            end_word(&mut tokens, &mut word_start, k);
+        } else {
+            if ch == '(' {
+                depth += 1;
+            }
+            word_start.get_or_insert(k);
+        }
+        k += 1;
+    }
+    end_word(&mut tokens, &mut word_start, chars.len());
+
+    Line {
+        number,
+        code,
+        tokens,
+    }
+}
+
+/// Найти закрывающую скобку для открывающей скобки с индексом open.
+fn matching_paren(chars: &[(usize, char)], open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (k, &(_, ch)) in chars.iter().enumerate().skip(open) {
+        match ch {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(k);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
@@ -0,0 +1,1493 @@
+// --------------------------------------------------
+// Разбор файла конфигурации L-системы
+// --------------------------------------------------
+
+use super::color::{self, COLOR_MODE_KEYWORDS, ColorMode, GradientStop};
+use super::expr::{self, Expr};
+use super::lexer::{self, Line, Pos, Span, TokenKind};
+use super::{
+    ActionDef, Actions, LSystemConfig, Module, ModulePattern, ModuleTemplate, NumberSources,
+    Production, Schedule, Symbol,
+};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+/*
+
+Вид файла конфигурации для L-системы:
+```
+<аксиома> <угол поворота в градусах> <начальное направление в градусах>
+<правило/действие/присвоение 1>
+...
+<правило/действие/присвоение N>
+```
+
+Формальная грамматика (расширенная БНФ). Каждая строка файла разбирается отдельно,
+пробелы между лексемами пропускаются:
+```
+файл          = { пустая-строка } заголовок { строка } ;
+строка        = ( [ секция | правило | действие | присвоение | константа ] ) [ комментарий ] ;
+комментарий   = "#" { любой-символ } ;
+заголовок     = аксиома число число ;
+секция        = "[rules]" | "[rules." имя "]" | "[actions]" | "[vars]" ;
+правило       = [ контекст "<" ] шаблон [ ">" контекст ] [ ":" выражение ] стрелка [ модули ] ;
+стрелка       = "->" | "-(" число ")->" ;
+действие      = шаблон "->" операция ;
+присвоение    = переменная "=" значение ;
+константа     = "DEFINE" имя "=" выражение ;
+аксиома       = модуль { модуль } ;
+модули        = модуль { модуль } ;
+модуль        = символ [ "(" выражение { "," выражение } ")" ] ;
+контекст      = шаблон { шаблон } ;
+шаблон        = символ [ "(" имя { "," имя } ")" ] ;
+символ        = любой-символ | имя ;
+имя           = ( буква | "_" ) { буква | цифра | "_" } ;
+```
+Внутри круглых скобок пробелы лексемы не разделяют. `->` выделяется всегда, а `<`, `>`, `:` и `=`
+являются разделителями только в левой части и не в начале строки (так, `< -> ROTATE +` - действие
+для символа `<`). `<` и `>` должны быть окружены пробелами, `=` не должен быть частью `==`, `<=`,
+`>=` или `!=`. Символ `#` всегда начинает комментарий и не может быть символом модуля.
+Параметры модулей аксиомы должны быть константами.
+
+Символом модуля может быть любой символ Unicode (`Ф`, `σ`) или имя из нескольких символов
+(`Branch`, `Лист`). Имена объявляются тем, что стоят в левой части правила или действия; в строках
+модулей они выделяются жадно (берётся самое длинное подходящее имя), поэтому при объявленных
+`Stem` и `Leaf` строка `F[+Stem]Leaf` - это модули `F [ + Stem ] Leaf`. Соседние модули можно
+разделять пробелами: `Stem Stem`. Имя, которое нигде не стоит в левой части, разбивается на
+отдельные символы.
+
+Секции необязательны. Вне секций строка со стрелкой - это действие, если её правая часть начинается
+с ключевого слова операции, и правило иначе; строка с `=` - присвоение. В секции `[rules]` правая
+часть стрелки всегда строка модулей (`X -> SAVE` - это модули S, A, V, E), в секции `[actions]` -
+всегда операция, а в секции `[vars]` допустимы только присвоения. Секции можно повторять в любом
+порядке.
+
+Таблицы правил (TOL-системы) объявляются секциями `[rules.<имя>]`: на каждой итерации применяется
+одна таблица, выбранная по расписанию `SCHEDULE = <имя>[*<число>], ...` (`A*2` - это `A, A`).
+Расписание повторяется по кругу (`SCHEDULE = A, A, B` - это `A A B A A B ...`), а если оно
+заканчивается на `<имя>*`, эта таблица применяется на всех остальных итерациях
+(`SCHEDULE = grow*5, bloom*` - пять итераций роста, потом цветение). Правила из `[rules]` и вне
+секций общие и действуют на всех итерациях, но правила таблицы заменяют общие правила того же
+символа. Без SCHEDULE таблицы применяются по кругу в порядке объявления.
+
+Правила имеют вид `<символ> -> <строка над алфавитом>`
+или `<символ> -(<вес>)-> <строка над алфавитом>`. У символа может быть несколько правил,
+тогда на каждой итерации одно из них выбирается случайно с вероятностью, пропорциональной весу
+(вес по умолчанию 1). Пустая правая часть (`F ->`) удаляет символ из строки.
+Перед символом правила можно указать контекст: `<левый контекст> < <символ> > <правый контекст> -> ...`
+(любая из частей контекста необязательна). Правило с совпавшим
+контекстом приоритетнее правил без контекста. При сопоставлении контекста пропускаются символы
+из переменной IGNORE и боковые ветви (символы с SAVE/RESTORE).
+Символы могут иметь параметры: `F(1,2)`. В левой части правил и действий параметрам даются имена
+(`A(t)`), в правой части правил параметры задаются выражениями над ними (`F(t*0.7)[+A(t-1)]`).
+Перед стрелкой правила можно указать условие: `A(t) : t>2 -> ...`. Шаблон без параметров подходит
+к символу с любым количеством параметров.
+Действия имеют вид `<символ> -> <операция>`
+Присвоения имеют вид `<переменная> = <значение>` - все они опциональны
+
+Операции имеют следующий вид:
+FORWARD <длина>
+| FORWARD RANDOM <мин. длина> <макс. длина>
+| MOVE <длина>
+| MOVE RANDOM <мин. длина> <макс. длина>
+| ROTATE (+ | -)
+| ROTATE <угол>
+| ROTATE RANDOM (+ | -) <дельта-угол>
+| PITCH (+ | - | <угол>)
+| ROLL (+ | - | <угол>)
+| TURN_AROUND
+| SET_HEADING <угол>
+| SET_WIDTH <толщина>
+| SCALE_LENGTH <коэффициент>
+| SCALE_WIDTH <коэффициент>
+| NEXT_COLOR
+| PREV_COLOR
+| SET_COLOR <номер>
+| SAVE
+| RESTORE
+| BEGIN_POLYGON
+| POLYGON_VERTEX
+| END_POLYGON
+
+MOVE перемещает чертёжника как FORWARD, но ничего не рисует (`f` из ABOP).
+ROTATE, PITCH и ROLL поворачивают чертёжника в пространстве вокруг его осей "вверх", "влево"
+и направления движения соответственно (`+ - & ^ \ /` из ABOP), TURN_AROUND - это `|`.
+`ROTATE <угол>` поворачивает на свой угол вместо общего угла из первой строки, а SET_HEADING
+задаёт абсолютное направление в плоскости холста (отсчитывается так же, как начальное направление).
+BEGIN_POLYGON, POLYGON_VERTEX и END_POLYGON - это `{ . }` из ABOP: позиции чертёжника, отмеченные
+POLYGON_VERTEX между BEGIN_POLYGON и END_POLYGON, образуют многоугольник, закрашенный текущим цветом.
+SCALE_LENGTH и SCALE_WIDTH умножают длину шагов и толщину линий; как и остальное состояние
+чертёжника, они сохраняются SAVE и восстанавливаются RESTORE, так что ветви могут быть короче
+и тоньше родительских.
+NEXT_COLOR, PREV_COLOR и SET_COLOR выбирают цвет из палитры PALETTE (номера идут по кругу
+с нуля) - это `' ;` из L-studio. Номер цвета тоже сохраняется SAVE и восстанавливается RESTORE.
+Длины, углы и толщины - выражения над параметрами символа (`+ - * / % ^`, сравнения, `&& || !`,
+функции `sqrt abs sin cos floor min max`).
+
+Константы объявляются строками `DEFINE <имя> = <выражение>` (например, `DEFINE STEP = 10`,
+`DEFINE RATIO = 0.618`) и могут стоять в любом месте файла после первой строки и в любой секции.
+Их можно использовать во всех выражениях файла: `F -> FORWARD STEP*RATIO`, `A(l) -> F(l*RATIO)`.
+Выражение константы может ссылаться на константы, объявленные выше; параметр модуля с тем же
+именем скрывает константу, а имена функций константам давать нельзя. Числа в первой строке, веса
+правил, отклонение `ROTATE RANDOM` и значения WIDTH и WIDTH_DELTA - тоже выражения, только без
+параметров: `F 360/7 0`, `X -(1/3)-> ...`, `WIDTH = STEP/4`.
+
+Переменные могут быть следующие:
+WIDTH
+| WIDTH_DELTA
+| COLOR
+| COLOR_DELTA
+| COLOR_MODE
+| HSV_DELTA
+| GRADIENT
+| PALETTE
+| IGNORE
+| SEED
+| SCHEDULE
+
+COLOR и COLOR_DELTA задаются как `(<r>, <g>, <b>)`, палитра - как `PALETTE = [(<r>, <g>, <b>), ...]`.
+
+COLOR_MODE выбирает, как меняется цвет линий. В режиме `RGB` (по умолчанию) после каждой линии
+к каналам цвета прибавляется COLOR_DELTA; каналы изменения могут быть отрицательными (от -255 до 255),
+а каналы цвета останавливаются на 0 и 255. В режиме `HSV` после каждой линии к тону (в градусах,
+по кругу), насыщенности и яркости (от 0 до 1) прибавляется `HSV_DELTA = (<тон>, <насыщенность>,
+<яркость>)`: например, `HSV_DELTA = (0.5, 0, 0)` проводит цвет по радуге. В режимах `GRADIENT_INDEX`
+и `GRADIENT_LENGTH` цвета `GRADIENT = [(<r>, <g>, <b>), ...]` расставляются на равных расстояниях
+от первой линии до последней, а цвет линии берётся по её номеру или по длине пути до её середины.
+Позиции цветов можно задать и явно, числами от 0 (первая линия) до 1 (последняя) по возрастанию:
+`GRADIENT = [(0, (20, 40, 160)), (0.3, (40, 160, 40)), (1, (200, 40, 40))]`.
+Цвет из палитры становится текущим и в режимах RGB и HSV, а в режимах градиента остаётся только
+у многоугольников.
+*/
+
+/// Ключевые слова операций.
+const OP_KEYWORDS: [&str; 18] = [
+    "FORWARD",
+    "MOVE",
+    "ROTATE",
+    "PITCH",
+    "ROLL",
+    "TURN_AROUND",
+    "SET_HEADING",
+    "SET_WIDTH",
+    "SCALE_LENGTH",
+    "SCALE_WIDTH",
+    "NEXT_COLOR",
+    "PREV_COLOR",
+    "SET_COLOR",
+    "SAVE",
+    "RESTORE",
+    "BEGIN_POLYGON",
+    "POLYGON_VERTEX",
+    "END_POLYGON",
+];
+
+/// Имена переменных.
+const VAR_KEYWORDS: [&str; 11] = [
+    "WIDTH",
+    "WIDTH_DELTA",
+    "COLOR",
+    "COLOR_DELTA",
+    "COLOR_MODE",
+    "HSV_DELTA",
+    "GRADIENT",
+    "PALETTE",
+    "IGNORE",
+    "SEED",
+    "SCHEDULE",
+];
+
+/// Ошибки во время пасринга конфига L-системы.
+///
+/// Каждая ошибка помнит фрагмент строки, к которому относится.
+#[derive(Debug)]
+pub enum LParseErr {
+    /// В пределах строки количество элементов отличается от ожидаемого.
+    UnexpectedValuesAmount(Span, usize, usize),
+    /// Полученное значение не соответсвует ожидаемому.
+    UnexpectedValue(Span, String, String),
+    /// Выражение не удалось разобрать.
+    InvalidExpression(Span, String, String),
+}
+
+impl LParseErr {
+    /// Фрагмент файла, в котором найдена ошибка.
+    pub fn span(&self) -> Span {
+        match self {
+            Self::UnexpectedValuesAmount(span, ..)
+            | Self::UnexpectedValue(span, ..)
+            | Self::InvalidExpression(span, ..) => *span,
+        }
+    }
+
+    /// Сообщение об ошибке без позиции в файле.
+    pub fn message(&self) -> String {
+        match self {
+            Self::UnexpectedValuesAmount(_, expected, got) => {
+                format!("ожидалось {} элементов, получено {}", expected, got)
+            }
+            Self::UnexpectedValue(_, expected, got) => {
+                format!("ожидалось {}, получено {}", expected, got)
+            }
+            Self::InvalidExpression(_, expr, reason) => {
+                format!("некорректное выражение '{}': {}", expr, reason)
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for LParseErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}: {}", self.span().start, self.message())
+    }
+}
+
+impl std::error::Error for LParseErr {}
+
+/// Все ошибки, найденные при разборе файла конфигурации (в порядке строк).
+#[derive(Debug)]
+pub struct LParseErrors(pub Vec<LParseErr>);
+
+impl std::fmt::Display for LParseErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for (i, err) in self.0.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", err)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for LParseErrors {}
+
+/// Секция файла конфигурации.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Section {
+    /// До первой секции: вид строки определяется по её содержимому.
+    Any,
+    /// `[rules]` - только правила.
+    Rules,
+    /// `[rules.<имя>]` - только правила таблицы с этим номером.
+    Table(usize),
+    /// `[actions]` - только действия.
+    Actions,
+    /// `[vars]` - только присвоения.
+    Vars,
+}
+
+/// Разобранная левая часть продукции или действия.
+struct LeftSide<'a> {
+    left_context: Vec<ModulePattern>,
+    predecessor: ModulePattern,
+    right_context: Vec<ModulePattern>,
+    /// Текст условия и его фрагмент строки.
+    condition: Option<(&'a str, Span)>,
+    /// Вес и его исходная запись.
+    weight: Option<(f32, &'a str)>,
+}
+
+impl LeftSide<'_> {
+    /// Есть ли в левой части то, что допустимо только у правил.
+    fn is_rule_only(&self) -> bool {
+        !self.left_context.is_empty()
+            || !self.right_context.is_empty()
+            || self.condition.is_some()
+            || self.weight.is_some()
+    }
+}
+
+pub struct Parser {
+    /// Разбираемый фрагмент строки (для сообщений об ошибках).
+    pub(super) span: Span,
+    /// Имена модулей из нескольких символов (от длинных к коротким).
+    pub(super) names: Vec<String>,
+    /// Именованные константы (`DEFINE`) в порядке объявления.
+    pub(super) constants: Vec<(String, f32)>,
+    /// Исходные выражения констант по их именам (для записи конфигурации обратно).
+    pub(super) constant_sources: HashMap<String, String>,
+    /// Расписание из SCHEDULE: имена таблиц по итерациям и повтор по кругу. Имена заменяются
+    /// номерами таблиц после разбора всего файла, потому что таблицы могут идти ниже.
+    pub(super) schedule: Option<(Span, Vec<String>, bool)>,
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Parser {
+    pub fn new() -> Self {
+        Self {
+            span: Span::default(),
+            names: Vec::new(),
+            constants: Vec::new(),
+            constant_sources: HashMap::new(),
+            schedule: None,
+        }
+    }
+
+    /// Разобрать файл с конфигурацией L-системы.
+    ///
+    /// Файлы `.json` и `.toml` разбираются как структурированные конфигурации,
+    /// остальные - как текстовый формат.
+    pub fn parse_l_system(
+        &mut self,
+        file_path: impl AsRef<Path>,
+    ) -> Result<LSystemConfig, Box<dyn std::error::Error>> {
+        let file_path = file_path.as_ref();
+        let text = std::fs::read_to_string(file_path)?;
+        self.parse_text(&text, file_path)
+    }
+
+    /// Разобрать текст конфигурации L-системы в формате, который определяется
+    /// по расширению имени файла file_name (как в parse_l_system).
+    pub fn parse_text(
+        &mut self,
+        text: &str,
+        file_name: impl AsRef<Path>,
+    ) -> Result<LSystemConfig, Box<dyn std::error::Error>> {
+        let extension = file_name
+            .as_ref()
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_lowercase);
+        match extension.as_deref() {
+            Some("json") => self.parse_json(text),
+            Some("toml") => self.parse_toml(text),
+            _ => self.parse_str(text),
+        }
+    }
+
+    /// Разобрать текст конфигурации L-системы.
+    ///
+    /// Ошибка в строке не останавливает разбор: остальные строки тоже проверяются,
+    /// и в случае ошибок возвращается LParseErrors со всеми найденными ошибками.
+    pub fn parse_str(&mut self, text: &str) -> Result<LSystemConfig, Box<dyn std::error::Error>> {
+        let lines = lexer::tokenize(text);
+        self.names = collect_names(lines.iter().skip(1));
+        let mut errors = Vec::new();
+        self.schedule = None;
+
+        // константы можно использовать в любой строке, поэтому они собираются заранее
+        self.constants.clear();
+        self.constant_sources.clear();
+        for line in lines.iter().skip(1).filter(|line| is_define(line)) {
+            if let Err(err) = self.parse_define(line) {
+                errors.push(err);
+            }
+        }
+        let mut lines = lines.iter();
+
+        // <аксиома> <угол поворота в градусах> <начальное направление в градусах>
+        let Some(header) = lines.next() else {
+            return Err(Box::new(LParseErrors(vec![LParseErr::UnexpectedValue(
+                Span::new(Pos { line: 1, col: 1 }, 0),
+                "<аксиома> <угол поворота> <начальное направление>".into(),
+                "пустой файл".into(),
+            )])));
+        };
+        // с ошибкой в заголовке остальные строки всё равно проверяются
+        let mut config = self.parse_header(header).unwrap_or_else(|err| {
+            errors.push(err);
+            empty_config(Vec::new(), 0.0, 0.0)
+        });
+        config.constants = self.constants.clone();
+        config.sources.constants = self.constant_sources.clone();
+
+        // <секция> | <правило> | <действие> | <присвоение>
+        let mut section = Section::Any;
+        for line in lines.filter(|line| !is_define(line)) {
+            match self.parse_section(line, &mut config) {
+                Ok(Some(next)) => section = next,
+                Ok(None) => {
+                    if let Err(err) = self.parse_line(line, section, &mut config) {
+                        errors.push(err);
+                    }
+                }
+                Err(err) => errors.push(err),
+            }
+        }
+        if let Err(err) = self.resolve_schedule(&mut config) {
+            errors.push(err);
+        }
+
+        if !errors.is_empty() {
+            // константы разбираются раньше остальных строк
+            errors.sort_by_key(|err| (err.span().start.line, err.span().start.col));
+            return Err(Box::new(LParseErrors(errors)));
+        }
+        Ok(config)
+    }
+
+    /// Разобрать строку правила, действия или присвоения внутри секции section.
+    pub(super) fn parse_line(
+        &mut self,
+        line: &Line,
+        section: Section,
+        config: &mut LSystemConfig,
+    ) -> Result<(), LParseErr> {
+        let separator = line
+            .tokens
+            .iter()
+            .position(|token| matches!(token.kind, TokenKind::Arrow | TokenKind::Assign));
+        match separator.map(|i| (i, line.tokens[i].kind)) {
+            Some((arrow, TokenKind::Arrow)) if section != Section::Vars => {
+                let is_action = match section {
+                    Section::Rules | Section::Table(_) => false,
+                    Section::Actions => true,
+                    _ => line
+                        .tokens
+                        .get(arrow + 1)
+                        .is_some_and(|token| OP_KEYWORDS.contains(&token.text)),
+                };
+                if is_action {
+                    self.parse_action(line, arrow, config)
+                } else {
+                    let table = match section {
+                        Section::Table(table) => Some(table),
+                        _ => None,
+                    };
+                    self.parse_rule(line, arrow, table, config)
+                }
+            }
+            Some((assign, TokenKind::Assign))
+                if matches!(section, Section::Any | Section::Vars) =>
+            {
+                self.parse_assignment(line, assign, config)
+            }
+            _ => {
+                let expected = match section {
+                    Section::Any => "<правило> | <действие> | <присвоение> | <секция>",
+                    Section::Rules | Section::Table(_) => "<правило>",
+                    Section::Actions => "<действие>",
+                    Section::Vars => "<присвоение>",
+                };
+                Err(LParseErr::UnexpectedValue(
+                    line.span_rest(0),
+                    expected.into(),
+                    line.code.trim().into(),
+                ))
+            }
+        }
+    }
+
+    /// Разобрать объявление константы: `DEFINE <имя> = <выражение>`.
+    ///
+    /// В выражении можно использовать константы, объявленные выше.
+    fn parse_define(&mut self, line: &Line) -> Result<(), LParseErr> {
+        let [_, name, assign, ..] = line.tokens.as_slice() else {
+            return Err(LParseErr::UnexpectedValue(
+                line.span_rest(0),
+                "DEFINE <имя> = <выражение>".into(),
+                line.code.trim().into(),
+            ));
+        };
+        if name.kind != TokenKind::Word || assign.kind != TokenKind::Assign {
+            return Err(LParseErr::UnexpectedValue(
+                line.span_rest(0),
+                "DEFINE <имя> = <выражение>".into(),
+                line.code.trim().into(),
+            ));
+        }
+
+        self.span = name.span();
+        self.check_constant_name(name.text)?;
+        self.span = line.span_rest(3);
+        let value = self.parse_number(line.rest(3))?;
+        self.constants.push((name.text.to_string(), value));
+        self.constant_sources
+            .insert(name.text.to_string(), line.rest(3).to_string());
+        Ok(())
+    }
+
+    /// Проверить, что name можно объявить константой: это имя, оно не занято функцией
+    /// и не объявлено раньше.
+    pub(super) fn check_constant_name(&self, name: &str) -> Result<(), LParseErr> {
+        if !is_identifier(name) || expr::is_function_name(name) {
+            return Err(LParseErr::UnexpectedValue(
+                self.span,
+                "<имя константы> (не имя функции)".into(),
+                name.into(),
+            ));
+        }
+        if self.constants.iter().any(|(known, _)| known == name) {
+            return Err(LParseErr::UnexpectedValue(
+                self.span,
+                "<новое имя константы>".into(),
+                format!("повторное объявление {}", name),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Разобрать первую строку: аксиому, угол поворота и начальное направление.
+    ///
+    /// Аксиома может состоять из нескольких слов (`F Leaf`), углы - последние два слова строки.
+    /// Возвращается конфигурация без правил и действий.
+    fn parse_header(&mut self, line: &Line) -> Result<LSystemConfig, LParseErr> {
+        let count = line.tokens.len();
+        if count < 3 || line.tokens.iter().any(|t| t.kind != TokenKind::Word) {
+            return Err(LParseErr::UnexpectedValuesAmount(
+                line.span_rest(0),
+                3,
+                count,
+            ));
+        }
+        self.span = line.span(0, count - 2);
+        let axiom = self.parse_axiom(line.slice(0, count - 2))?;
+        self.span = line.tokens[count - 2].span();
+        let rotate_angle = self.parse_number(line.tokens[count - 2].text)?;
+        self.span = line.tokens[count - 1].span();
+        let angle = self.parse_number(line.tokens[count - 1].text)?;
+
+        let mut config = empty_config(axiom, rotate_angle, angle);
+        config.sources.rotate_angle = Some(line.tokens[count - 2].text.to_string());
+        config.sources.angle = Some(line.tokens[count - 1].text.to_string());
+        Ok(config)
+    }
+
+    /// Разобрать заголовок секции `[<имя>]`, если строка им является.
+    ///
+    /// Секция `[rules.<имя>]` добавляет в config таблицу правил, если её ещё нет.
+    fn parse_section(
+        &mut self,
+        line: &Line,
+        config: &mut LSystemConfig,
+    ) -> Result<Option<Section>, LParseErr> {
+        let [token] = line.tokens.as_slice() else {
+            return Ok(None);
+        };
+        let Some(name) = token
+            .text
+            .strip_prefix('[')
+            .and_then(|text| text.strip_suffix(']'))
+        else {
+            return Ok(None);
+        };
+        if let Some(table) = name.strip_prefix("rules.")
+            && is_identifier(table)
+        {
+            return Ok(Some(Section::Table(config.table_or_insert(table))));
+        }
+        if name.is_empty() || !name.chars().all(char::is_alphabetic) {
+            return Ok(None);
+        }
+        match name {
+            "rules" => Ok(Some(Section::Rules)),
+            "actions" => Ok(Some(Section::Actions)),
+            "vars" => Ok(Some(Section::Vars)),
+            _ => Err(LParseErr::UnexpectedValue(
+                token.span(),
+                "[rules] | [rules.<имя>] | [actions] | [vars]".into(),
+                token.text.into(),
+            )),
+        }
+    }
+
+    /// Разобрать левую часть продукции или действия (лексемы до стрелки arrow).
+    ///
+    /// `[<левый контекст> <] <модуль> [> <правый контекст>] [: <условие>] (-> | -(<вес>)->)`
+    fn parse_left_side<'a>(
+        &mut self,
+        line: &Line<'a>,
+        arrow: usize,
+    ) -> Result<LeftSide<'a>, LParseErr> {
+        let find = |kind| line.tokens[..arrow].iter().position(|t| t.kind == kind);
+        let left = find(TokenKind::LeftContext);
+        let right = find(TokenKind::RightContext);
+        let colon = find(TokenKind::Colon);
+
+        // [<левый контекст> <]
+        let left_context = match left {
+            Some(left) => {
+                self.span = line.span(0, left);
+                self.parse_patterns(line.slice(0, left))?
+            }
+            None => Vec::new(),
+        };
+
+        // <модуль>
+        let from = left.map_or(0, |left| left + 1);
+        let to = right.or(colon).unwrap_or(arrow);
+        self.span = line.span(from, to);
+        let mut predecessor = self.parse_patterns(line.slice(from, to))?;
+        if predecessor.len() != 1 {
+            return Err(LParseErr::UnexpectedValue(
+                self.span,
+                "<одиночный модуль>".into(),
+                format!("'{}'", line.slice(from, to)),
+            ));
+        }
+        let predecessor = predecessor.remove(0);
+
+        // [> <правый контекст>]
+        let right_context = match right {
+            Some(right) => {
+                let to = colon.unwrap_or(arrow);
+                self.span = line.span(right + 1, to);
+                self.parse_patterns(line.slice(right + 1, to))?
+            }
+            None => Vec::new(),
+        };
+
+        // [: <условие>]
+        let condition = match colon {
+            Some(colon) => {
+                let condition = line.slice(colon + 1, arrow);
+                if condition.is_empty() {
+                    return Err(LParseErr::UnexpectedValue(
+                        line.tokens[colon].span(),
+                        "<условие>".into(),
+                        "''".into(),
+                    ));
+                }
+                Some((condition, line.span(colon + 1, arrow)))
+            }
+            None => None,
+        };
+
+        // -(<вес>)->
+        let weight = match line.tokens[arrow].weight() {
+            Some(source) => {
+                self.span = line.tokens[arrow].span();
+                let weight = self.parse_number(source)?;
+                if !weight.is_finite() || weight <= 0.0 {
+                    return Err(LParseErr::UnexpectedValue(
+                        self.span,
+                        "<положительный вес>".into(),
+                        weight.to_string(),
+                    ));
+                }
+                Some((weight, source))
+            }
+            None => None,
+        };
+
+        // в правой части может быть только одна стрелка
+        if let Some(token) = line.tokens[arrow + 1..]
+            .iter()
+            .find(|token| token.kind == TokenKind::Arrow)
+        {
+            return Err(LParseErr::UnexpectedValue(
+                token.span(),
+                "<строка модулей> | <операция>".into(),
+                token.text.into(),
+            ));
+        }
+
+        Ok(LeftSide {
+            left_context,
+            predecessor,
+            right_context,
+            condition,
+            weight,
+        })
+    }
+
+    /// Разобрать правило `<левая часть> -> <строка модулей>` и добавить его
+    /// в таблицу table (None - в общие правила).
+    fn parse_rule(
+        &mut self,
+        line: &Line,
+        arrow: usize,
+        table: Option<usize>,
+        config: &mut LSystemConfig,
+    ) -> Result<(), LParseErr> {
+        let left_side = self.parse_left_side(line, arrow)?;
+
+        // имена формальных параметров в порядке: левый контекст, модуль, правый контекст
+        let formals: Vec<String> = left_side
+            .left_context
+            .iter()
+            .chain(std::iter::once(&left_side.predecessor))
+            .chain(&left_side.right_context)
+            .flat_map(|p| p.params.iter().cloned())
+            .collect();
+
+        let condition = match left_side.condition {
+            Some((condition, span)) => {
+                self.span = span;
+                Some(self.parse_expr(condition, &formals)?)
+            }
+            None => None,
+        };
+
+        // [<строка модулей>] <- но самой проверки алфавита не будет, у меня лапки
+        // (пустая правая часть удаляет модуль из строки)
+        let rv = line.rest(arrow + 1);
+        self.span = line.span_rest(arrow + 1);
+        let successor = self.parse_templates(rv, &formals)?;
+
+        let symbol = left_side.predecessor.symbol.clone();
+        let rules = match table {
+            Some(table) => &mut config.tables[table].rules,
+            None => &mut config.rules,
+        };
+        let productions = rules.entry(symbol.clone()).or_default();
+        if let Some((_, source)) = left_side.weight {
+            config
+                .sources
+                .weights
+                .insert((table, symbol, productions.len()), source.to_string());
+        }
+        productions.push(Production {
+            left_context: left_side.left_context,
+            predecessor: left_side.predecessor,
+            right_context: left_side.right_context,
+            condition,
+            successor,
+            weight: left_side.weight.map_or(1.0, |(weight, _)| weight),
+        });
+        Ok(())
+    }
+
+    /// Разобрать действие: `<модуль> -> <операция>`.
+    fn parse_action(
+        &mut self,
+        line: &Line,
+        arrow: usize,
+        config: &mut LSystemConfig,
+    ) -> Result<(), LParseErr> {
+        let rotate_angle = config.rotate_angle;
+        let left_side = self.parse_left_side(line, arrow)?;
+        if left_side.is_rule_only() {
+            return Err(LParseErr::UnexpectedValue(
+                line.span(0, arrow + 1),
+                "<модуль> (вес, контекст и условие допустимы только у правил)".into(),
+                line.slice(0, arrow + 1).into(),
+            ));
+        }
+        let symbol = left_side.predecessor.symbol;
+        let params = left_side.predecessor.params;
+
+        // <ключевое слово> <аргументы>
+        let Some(keyword) = line
+            .tokens
+            .get(arrow + 1)
+            .filter(|token| OP_KEYWORDS.contains(&token.text))
+        else {
+            return Err(LParseErr::UnexpectedValue(
+                line.span_rest(arrow + 1),
+                OP_KEYWORDS.join(" | "),
+                line.rest(arrow + 1).into(),
+            ));
+        };
+        let random = line
+            .tokens
+            .get(arrow + 2)
+            .is_some_and(|token| token.text == "RANDOM");
+        let args_from = if random { arrow + 3 } else { arrow + 2 };
+        let args = line.rest(args_from);
+        self.span = line.span_rest(args_from);
+
+        let action = match keyword.text {
+            // (FORWARD | MOVE) RANDOM <начало диапазона> <конец диапазона>
+            "FORWARD" | "MOVE" if random => {
+                let range = &line.tokens[args_from..];
+                if range.len() != 2 {
+                    return Err(LParseErr::UnexpectedValue(
+                        self.span,
+                        "<начало диапазона> <конец диапазона>".into(),
+                        format!("'{}'", args),
+                    ));
+                }
+                self.span = range[0].span();
+                let range_begin = self.parse_expr(range[0].text, &params)?;
+                self.span = range[1].span();
+                let range_end = self.parse_expr(range[1].text, &params)?;
+                if keyword.text == "FORWARD" {
+                    Actions::ForwardRandom(range_begin, range_end)
+                } else {
+                    Actions::MoveRandom(range_begin, range_end)
+                }
+            }
+            // (FORWARD | MOVE) <длина>
+            "FORWARD" | "MOVE" => {
+                let length = self.parse_expr(self.expect_args(args, "<длина>")?, &params)?;
+                if keyword.text == "FORWARD" {
+                    Actions::Forward(length)
+                } else {
+                    Actions::Move(length)
+                }
+            }
+            // ROTATE RANDOM (- | +) <отклонение>
+            "ROTATE" if random => {
+                let args = self.expect_args(args, "(- | +) <отклонение>")?;
+                let (sign, delta) = match args.strip_prefix('-') {
+                    Some(delta) => (-1.0, delta),
+                    None => (1.0, args.strip_prefix('+').unwrap_or(args)),
+                };
+                let source = self.expect_args(delta.trim(), "<отклонение>")?;
+                let delta = self.parse_number(source)?;
+                config
+                    .sources
+                    .random_deltas
+                    .insert(symbol.clone(), source.to_string());
+                Actions::RotateRandom(sign * rotate_angle, delta)
+            }
+            // ROTATE (+ | - | <угол>)
+            "ROTATE" => {
+                let args = self.expect_args(args, "+ | - | <угол>")?;
+                Actions::Rotate(self.parse_angle(args, rotate_angle, &params)?)
+            }
+            // (PITCH | ROLL) (+ | - | <угол>)
+            "PITCH" | "ROLL" => {
+                let args = self.expect_args(args, "+ | - | <угол>")?;
+                let angle = self.parse_angle(args, rotate_angle, &params)?;
+                if keyword.text == "PITCH" {
+                    Actions::Pitch(angle)
+                } else {
+                    Actions::Roll(angle)
+                }
+            }
+            // SET_HEADING <угол>
+            "SET_HEADING" => {
+                Actions::SetHeading(self.parse_expr(self.expect_args(args, "<угол>")?, &params)?)
+            }
+            // SET_WIDTH <толщина>
+            "SET_WIDTH" => {
+                Actions::SetWidth(self.parse_expr(self.expect_args(args, "<толщина>")?, &params)?)
+            }
+            // (SCALE_LENGTH | SCALE_WIDTH) <коэффициент>
+            "SCALE_LENGTH" | "SCALE_WIDTH" => {
+                let k = self.parse_expr(self.expect_args(args, "<коэффициент>")?, &params)?;
+                if keyword.text == "SCALE_LENGTH" {
+                    Actions::ScaleLength(k)
+                } else {
+                    Actions::ScaleWidth(k)
+                }
+            }
+            // SET_COLOR <номер>
+            "SET_COLOR" => {
+                Actions::SetColor(self.parse_expr(self.expect_args(args, "<номер>")?, &params)?)
+            }
+            // операции без аргументов
+            keyword => {
+                if !args.is_empty() || random {
+                    return Err(LParseErr::UnexpectedValue(
+                        line.span_rest(arrow + 2),
+                        "''".into(),
+                        line.rest(arrow + 2).into(),
+                    ));
+                }
+                match keyword {
+                    "TURN_AROUND" => Actions::TurnAround,
+                    "NEXT_COLOR" => Actions::NextColor,
+                    "PREV_COLOR" => Actions::PrevColor,
+                    "SAVE" => Actions::Save,
+                    "RESTORE" => Actions::Restore,
+                    "BEGIN_POLYGON" => Actions::BeginPolygon,
+                    "POLYGON_VERTEX" => Actions::PolygonVertex,
+                    "END_POLYGON" => Actions::EndPolygon,
+                    _ => {
+                        return Err(LParseErr::UnexpectedValue(
+                            line.tokens[arrow + 1].span(),
+                            OP_KEYWORDS.join(" | "),
+                            keyword.into(),
+                        ));
+                    }
+                }
+            }
+        };
+        config.actions.insert(symbol, ActionDef { params, action });
+        Ok(())
+    }
+
+    /// Разобрать присвоение: `<переменная> = <значение>`.
+    fn parse_assignment(
+        &mut self,
+        line: &Line,
+        assign: usize,
+        config: &mut LSystemConfig,
+    ) -> Result<(), LParseErr> {
+        // <переменная>
+        let lv = line.slice(0, assign);
+        if assign != 1 || !VAR_KEYWORDS.contains(&lv) {
+            return Err(LParseErr::UnexpectedValue(
+                line.span(0, assign),
+                VAR_KEYWORDS.join(" | "),
+                lv.into(),
+            ));
+        }
+
+        // <значение>
+        let rv = line.rest(assign + 1);
+        self.span = line.span_rest(assign + 1);
+        let rv = self.expect_args(rv, "<значение>")?;
+
+        match lv {
+            // WIDTH = <float32>
+            "WIDTH" => {
+                config.width = self.parse_number(rv)?;
+                config.sources.width = Some(rv.to_string());
+            }
+            // WIDTH_DELTA = <float32>
+            "WIDTH_DELTA" => {
+                config.width_delta = self.parse_number(rv)?;
+                config.sources.width_delta = Some(rv.to_string());
+            }
+            // SCHEDULE = <имя>[*<число>], ...
+            "SCHEDULE" => {
+                let (names, cycle) = self.parse_schedule(rv)?;
+                self.schedule = Some((self.span, names, cycle));
+            }
+            // SEED = <u64>
+            "SEED" => {
+                config.seed = Some(rv.parse().map_err(|_| {
+                    LParseErr::UnexpectedValue(
+                        self.span,
+                        "<целое неотрицательное число>".into(),
+                        rv.into(),
+                    )
+                })?)
+            }
+            // IGNORE = <символы>
+            "IGNORE" => {
+                for (symbol, args) in self.split_modules(rv)? {
+                    if !args.is_empty() {
+                        return Err(LParseErr::UnexpectedValue(
+                            self.span,
+                            "<символы без параметров>".into(),
+                            rv.into(),
+                        ));
+                    }
+                    config.ignore.insert(symbol);
+                }
+            }
+            // PALETTE = [(<r>, <g>, <b>), ...]
+            "PALETTE" => config.palette = self.parse_palette(rv)?,
+            // COLOR = (<r>, <g>, <b>)
+            "COLOR" => config.color = self.parse_color(rv)?,
+            // COLOR_DELTA = (<r>, <g>, <b>)
+            "COLOR_DELTA" => config.color_delta = self.parse_color_delta(rv)?,
+            // COLOR_MODE = RGB | HSV | GRADIENT_INDEX | GRADIENT_LENGTH
+            "COLOR_MODE" => {
+                config.color_mode = ColorMode::from_keyword(rv).ok_or_else(|| {
+                    LParseErr::UnexpectedValue(
+                        self.span,
+                        COLOR_MODE_KEYWORDS.join(" | "),
+                        rv.into(),
+                    )
+                })?
+            }
+            // HSV_DELTA = (<тон>, <насыщенность>, <яркость>)
+            "HSV_DELTA" => {
+                let [hue, saturation, value] = self.split_triple(rv, "(<h>, <s>, <v>)")?;
+                config.hsv_delta = [
+                    self.parse_number(hue)?,
+                    self.parse_number(saturation)?,
+                    self.parse_number(value)?,
+                ];
+            }
+            // GRADIENT = [(<r>, <g>, <b>), ...] | [(<позиция>, (<r>, <g>, <b>)), ...]
+            "GRADIENT" => config.gradient = self.parse_gradient(rv)?,
+            _ => {
+                return Err(LParseErr::UnexpectedValue(
+                    line.span(0, assign),
+                    VAR_KEYWORDS.join(" | "),
+                    lv.into(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Разобрать расписание таблиц: `<имя>[*<число>], ...`. Последним может быть `<имя>*`.
+    ///
+    /// Возвращает имена таблиц по итерациям (`A*2` - это `A, A`) и признак повтора по кругу:
+    /// расписание повторяется, если не заканчивается на `<имя>*`.
+    pub(super) fn parse_schedule(&self, text: &str) -> Result<(Vec<String>, bool), LParseErr> {
+        let items: Vec<&str> = text.split(',').map(str::trim).collect();
+        let mut names = Vec::new();
+        let mut cycle = true;
+        for (i, item) in items.iter().enumerate() {
+            let (name, count) = match item.split_once('*') {
+                Some((name, count)) => (name.trim(), Some(count.trim())),
+                None => (*item, None),
+            };
+            if !is_identifier(name) {
+                return Err(LParseErr::UnexpectedValue(
+                    self.span,
+                    "<имя таблицы>[*<число>]".into(),
+                    item.to_string(),
+                ));
+            }
+            let count = match count {
+                None => 1,
+                // `<имя>*` в конце: эта таблица применяется на всех остальных итерациях
+                Some("") if i + 1 == items.len() => {
+                    cycle = false;
+                    1
+                }
+                Some(count) => count
+                    .parse()
+                    .ok()
+                    .filter(|&count| count > 0)
+                    .ok_or_else(|| {
+                        LParseErr::UnexpectedValue(
+                            self.span,
+                            "<имя таблицы>*<целое положительное число>".into(),
+                            item.to_string(),
+                        )
+                    })?,
+            };
+            names.extend(std::iter::repeat_n(name.to_string(), count));
+        }
+        Ok((names, cycle))
+    }
+
+    /// Заменить имена таблиц в расписании из SCHEDULE номерами таблиц config.
+    ///
+    /// Без SCHEDULE таблицы применяются по кругу в порядке объявления.
+    pub(super) fn resolve_schedule(&mut self, config: &mut LSystemConfig) -> Result<(), LParseErr> {
+        let Some((span, names, cycle)) = self.schedule.take() else {
+            config.schedule = Schedule {
+                steps: (0..config.tables.len()).collect(),
+                cycle: true,
+            };
+            return Ok(());
+        };
+        let mut steps = Vec::with_capacity(names.len());
+        for name in names {
+            let Some(table) = config.tables.iter().position(|table| table.name == name) else {
+                return Err(LParseErr::UnexpectedValue(
+                    span,
+                    "<имя таблицы из [rules.<имя>]>".into(),
+                    name,
+                ));
+            };
+            steps.push(table);
+        }
+        config.schedule = Schedule { steps, cycle };
+        Ok(())
+    }
+
+    /// Проверить, что у операции или переменной есть аргументы.
+    fn expect_args<'a>(&self, args: &'a str, expected: &str) -> Result<&'a str, LParseErr> {
+        if args.is_empty() {
+            return Err(LParseErr::UnexpectedValue(
+                self.span,
+                expected.into(),
+                "''".into(),
+            ));
+        }
+        Ok(args)
+    }
+
+    /// Разобрать число: выражение без параметров (`10`, `360/7`, `STEP*RATIO`).
+    fn parse_number(&self, text: &str) -> Result<f32, LParseErr> {
+        if text.trim().is_empty() {
+            return Err(LParseErr::UnexpectedValue(
+                self.span,
+                "<число>".into(),
+                "''".into(),
+            ));
+        }
+        Ok(self.parse_expr(text, &[])?.eval(&[]))
+    }
+
+    /// Разбить тройку чисел вида `(<a>, <b>, <c>)` на тексты чисел.
+    fn split_triple<'a>(&self, text: &'a str, expected: &str) -> Result<[&'a str; 3], LParseErr> {
+        let Some(inner) = text
+            .trim()
+            .strip_prefix('(')
+            .and_then(|text| text.strip_suffix(')'))
+        else {
+            return Err(LParseErr::UnexpectedValue(
+                self.span,
+                expected.into(),
+                text.into(),
+            ));
+        };
+        let items: Vec<&str> = inner.split(',').map(str::trim).collect();
+        let count = items.len();
+        items
+            .try_into()
+            .map_err(|_| LParseErr::UnexpectedValuesAmount(self.span, 3, count))
+    }
+
+    /// Разобрать цвет вида `(<r>, <g>, <b>)`.
+    fn parse_color(&self, text: &str) -> Result<egui::Color32, LParseErr> {
+        let rgb = self.split_triple(text, "(<r>, <g>, <b>)")?;
+        let mut channels = [0; 3];
+        for (channel, text) in channels.iter_mut().zip(rgb) {
+            *channel = text.parse().map_err(|_| {
+                LParseErr::UnexpectedValue(self.span, "<число от 0 до 255>".into(), text.into())
+            })?;
+        }
+        Ok(egui::Color32::from_rgb(
+            channels[0],
+            channels[1],
+            channels[2],
+        ))
+    }
+
+    /// Разобрать изменение цвета вида `(<r>, <g>, <b>)`: каналы могут быть отрицательными.
+    fn parse_color_delta(&self, text: &str) -> Result<[i16; 3], LParseErr> {
+        let rgb = self.split_triple(text, "(<r>, <g>, <b>)")?;
+        let mut channels = [0; 3];
+        for (channel, text) in channels.iter_mut().zip(rgb) {
+            *channel = text
+                .parse()
+                .ok()
+                .filter(|delta: &i16| (-255..=255).contains(delta))
+                .ok_or_else(|| {
+                    LParseErr::UnexpectedValue(
+                        self.span,
+                        "<число от -255 до 255>".into(),
+                        text.into(),
+                    )
+                })?;
+        }
+        Ok(channels)
+    }
+
+    /// Разобрать градиент: цвета на равных расстояниях `[(<r>, <g>, <b>), ...]`
+    /// или точки с позициями от 0 до 1 `[(<позиция>, (<r>, <g>, <b>)), ...]`.
+    fn parse_gradient(&self, text: &str) -> Result<Vec<GradientStop>, LParseErr> {
+        let Some(items) = text
+            .trim()
+            .strip_prefix('[')
+            .and_then(|text| text.strip_suffix(']'))
+        else {
+            return Err(LParseErr::UnexpectedValue(
+                self.span,
+                "[(<r>, <g>, <b>), ...] | [(<позиция>, (<r>, <g>, <b>)), ...]".into(),
+                text.into(),
+            ));
+        };
+        let items = self.split_items(items)?;
+
+        // у точки с позицией внутри внешних скобок есть ещё одни
+        let explicit = |item: &str| item.trim_start_matches('(').contains('(');
+        if !items.iter().any(|item| explicit(item)) {
+            let colors = items
+                .iter()
+                .map(|item| self.parse_color(item))
+                .collect::<Result<_, _>>()?;
+            return Ok(color::evenly_spaced(colors));
+        }
+
+        let mut stops = Vec::new();
+        for item in &items {
+            let stop = item
+                .strip_prefix('(')
+                .and_then(|item| item.strip_suffix(')'))
+                .and_then(|item| item.split_once(','))
+                .filter(|_| explicit(item));
+            let Some((position, rgb)) = stop else {
+                return Err(LParseErr::UnexpectedValue(
+                    self.span,
+                    "(<позиция>, (<r>, <g>, <b>))".into(),
+                    item.to_string(),
+                ));
+            };
+            stops.push((self.parse_number(position)?, self.parse_color(rgb)?));
+        }
+        if let Some(i) = color::invalid_gradient_stop(&stops) {
+            return Err(LParseErr::UnexpectedValue(
+                self.span,
+                "<позиция от 0 до 1, не меньше предыдущей>".into(),
+                items[i].to_string(),
+            ));
+        }
+        Ok(stops)
+    }
+
+    /// Разбить список через запятую на элементы. Запятые внутри скобок элементы не разделяют.
+    fn split_items<'a>(&self, text: &'a str) -> Result<Vec<&'a str>, LParseErr> {
+        let mut items = Vec::new();
+        let mut depth = 0usize;
+        let mut start = 0;
+        for (i, ch) in text.char_indices() {
+            match ch {
+                '(' => depth += 1,
+                ')' => {
+                    depth = depth.checked_sub(1).ok_or_else(|| {
+                        LParseErr::UnexpectedValue(self.span, "'('".into(), text[..=i].into())
+                    })?
+                }
+                ',' if depth == 0 => {
+                    items.push(text[start..i].trim());
+                    start = i + 1;
+                }
+                _ => {}
+            }
+        }
+        if depth > 0 {
+            return Err(LParseErr::UnexpectedValue(
+                self.span,
+                "')'".into(),
+                text[start..].trim().into(),
+            ));
+        }
+        // после последнего элемента может стоять запятая
+        let last = text[start..].trim();
+        if !last.is_empty() {
+            items.push(last);
+        }
+        Ok(items)
+    }
+
+    /// Разобрать палитру вида `[(<r>, <g>, <b>), ...]`.
+    fn parse_palette(&self, text: &str) -> Result<Vec<egui::Color32>, LParseErr> {
+        let Some(colors) = text
+            .trim()
+            .strip_prefix('[')
+            .and_then(|text| text.strip_suffix(']'))
+        else {
+            return Err(LParseErr::UnexpectedValue(
+                self.span,
+                "[(<r>, <g>, <b>), ...]".into(),
+                text.into(),
+            ));
+        };
+
+        // цвета разделяются запятыми после закрывающей скобки
+        let mut palette = Vec::new();
+        let mut rest = colors.trim();
+        while !rest.is_empty() {
+            let Some(end) = rest.find(')') else {
+                return Err(LParseErr::UnexpectedValue(
+                    self.span,
+                    "')'".into(),
+                    rest.into(),
+                ));
+            };
+            palette.push(self.parse_color(&rest[..=end])?);
+            rest = rest[end + 1..].trim_start();
+            if let Some(next) = rest.strip_prefix(',') {
+                rest = next.trim_start();
+            } else if !rest.is_empty() {
+                return Err(LParseErr::UnexpectedValue(
+                    self.span,
+                    "','".into(),
+                    rest.into(),
+                ));
+            }
+        }
+        Ok(palette)
+    }
+
+    /// Разобрать аксиому: параметры модулей аксиомы должны быть константами.
+    pub(super) fn parse_axiom(&self, text: &str) -> Result<Vec<Module>, LParseErr> {
+        let mut axiom = Vec::new();
+        for (symbol, args) in self.split_modules(text)? {
+            let mut params = Vec::with_capacity(args.len());
+            for arg in args {
+                params.push(self.parse_expr(&arg, &[])?.eval(&[]));
+            }
+            axiom.push(Module { symbol, params });
+        }
+        Ok(axiom)
+    }
+
+    /// Разобрать шаблоны модулей левой части: параметры должны быть именами.
+    fn parse_patterns(&self, text: &str) -> Result<Vec<ModulePattern>, LParseErr> {
+        let mut patterns = Vec::new();
+        for (symbol, args) in self.split_modules(text)? {
+            let mut params = Vec::with_capacity(args.len());
+            for arg in args {
+                let name = arg.trim();
+                if !is_identifier(name) {
+                    return Err(LParseErr::UnexpectedValue(
+                        self.span,
+                        "<имя параметра>".into(),
+                        name.into(),
+                    ));
+                }
+                params.push(name.to_string());
+            }
+            patterns.push(ModulePattern { symbol, params });
+        }
+        Ok(patterns)
+    }
+
+    /// Разобрать модули правой части: параметры задаются выражениями над formals.
+    fn parse_templates(
+        &self,
+        text: &str,
+        formals: &[String],
+    ) -> Result<Vec<ModuleTemplate>, LParseErr> {
+        let mut templates = Vec::new();
+        for (symbol, args) in self.split_modules(text)? {
+            let mut exprs = Vec::with_capacity(args.len());
+            for arg in args {
+                exprs.push(self.parse_expr(&arg, formals)?);
+            }
+            templates.push(ModuleTemplate {
+                symbol,
+                args: exprs,
+            });
+        }
+        Ok(templates)
+    }
+
+    /// Разобрать угол поворота: `+` и `-` означают угол из первой строки файла,
+    /// иначе это выражение с переменными из params.
+    fn parse_angle(
+        &self,
+        text: &str,
+        rotate_angle: f32,
+        params: &[String],
+    ) -> Result<Expr, LParseErr> {
+        match text {
+            "+" => Ok(Expr::constant(rotate_angle)),
+            "-" => Ok(Expr::constant(-rotate_angle)),
+            _ => self.parse_expr(text, params),
+        }
+    }
+
+    /// Разобрать выражение с переменными из params и объявленными константами.
+    fn parse_expr(&self, text: &str, params: &[String]) -> Result<Expr, LParseErr> {
+        Expr::parse(text, params, &self.constants)
+            .map_err(|reason| LParseErr::InvalidExpression(self.span, text.trim().into(), reason))
+    }
+
+    /// Разбить строку модулей на символы и тексты их аргументов.
+    ///
+    /// Пробелы между модулями пропускаются, аргументы перечисляются через запятую в скобках.
+    /// Имена из нескольких символов выделяются жадно: из известных имён берётся самое длинное,
+    /// с которого начинается остаток строки, иначе модулем становится один символ.
+    pub(super) fn split_modules(&self, text: &str) -> Result<Vec<RawModule>, LParseErr> {
+        let chars: Vec<char> = text.chars().collect();
+        let mut modules = Vec::new();
+        let mut i = 0;
+        while i < chars.len() {
+            let ch = chars[i];
+            if ch.is_whitespace() {
+                i += 1;
+                continue;
+            }
+            if ch == '(' || ch == ')' {
+                return Err(LParseErr::UnexpectedValue(
+                    self.span,
+                    "<символ>".into(),
+                    ch.to_string(),
+                ));
+            }
+            let symbol = match self.names.iter().find(|name| {
+                chars[i..]
+                    .iter()
+                    .copied()
+                    .take(name.chars().count())
+                    .eq(name.chars())
+            }) {
+                Some(name) => {
+                    i += name.chars().count();
+                    Symbol::new(name)
+                }
+                None => {
+                    i += 1;
+                    Symbol::Char(ch)
+                }
+            };
+
+            let mut args = Vec::new();
+            if chars.get(i) == Some(&'(') {
+                i += 1;
+                let mut depth = 1;
+                let mut arg = String::new();
+                loop {
+                    let Some(&ch) = chars.get(i) else {
+                        return Err(LParseErr::UnexpectedValue(
+                            self.span,
+                            "')'".into(),
+                            text.into(),
+                        ));
+                    };
+                    i += 1;
+                    match ch {
+                        '(' => depth += 1,
+                        ')' => depth -= 1,
+                        ',' if depth == 1 => {
+                            args.push(std::mem::take(&mut arg));
+                            continue;
+                        }
+                        _ => {}
+                    }
+                    if depth == 0 {
+                        args.push(arg);
+                        break;
+                    }
+                    arg.push(ch);
+                }
+            }
+            modules.push((symbol, args));
+        }
+        Ok(modules)
+    }
+}
+
+/// Конфигурация без правил и действий, со значениями переменных по умолчанию.
+pub(super) fn empty_config(axiom: Vec<Module>, rotate_angle: f32, angle: f32) -> LSystemConfig {
+    LSystemConfig {
+        axiom,
+        rules: HashMap::new(),
+        tables: Vec::new(),
+        schedule: Schedule::default(),
+        actions: HashMap::new(),
+        ignore: HashSet::new(),
+        seed: None,
+        width: 1.0,
+        width_delta: 0.0,
+        rotate_angle,
+        angle,
+        color: egui::Color32::BLACK,
+        color_mode: ColorMode::Rgb,
+        color_delta: [0; 3],
+        hsv_delta: [0.0; 3],
+        gradient: Vec::new(),
+        palette: Vec::new(),
+        constants: Vec::new(),
+        sources: NumberSources::default(),
+    }
+}
+
+/// Является ли текст именем: `( буква | "_" ) { буква | цифра | "_" }`.
+pub(super) fn is_identifier(text: &str) -> bool {
+    text.starts_with(|ch: char| ch.is_alphabetic() || ch == '_')
+        && text.chars().all(|ch| ch.is_alphanumeric() || ch == '_')
+}
+
+/// Является ли строка объявлением константы (`DEFINE ...`).
+fn is_define(line: &Line) -> bool {
+    line.tokens
+        .first()
+        .is_some_and(|token| token.text == "DEFINE")
+}
+
+/// Символ модуля и неразобранные тексты его аргументов.
+type RawModule = (Symbol, Vec<String>);
+
+/// Собрать имена модулей из нескольких символов: имена в левых частях правил и действий.
+///
+/// Имя - это идентификатор из букв, цифр и `_`, начинающийся не с цифры.
+pub(super) fn collect_names<'a>(lines: impl Iterator<Item = &'a Line<'a>>) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for line in lines {
+        let Some(arrow) = line.tokens.iter().position(|t| t.kind == TokenKind::Arrow) else {
+            continue;
+        };
+        let find = |kind| line.tokens[..arrow].iter().position(|t| t.kind == kind);
+        let from = find(TokenKind::LeftContext).map_or(0, |left| left + 1);
+        let to = find(TokenKind::RightContext)
+            .or(find(TokenKind::Colon))
+            .unwrap_or(arrow);
+        let predecessor = line.slice(from, to);
+        let name = predecessor
+            .split_once('(')
+            .map_or(predecessor, |(name, _)| name)
+            .trim();
+        let is_name = name.chars().count() > 1 && is_identifier(name);
+        if is_name && !names.iter().any(|known| known == name) {
+            names.push(name.to_string());
+        }
+    }
+    // сначала длинные имена, чтобы `Leaf` не разбилось на `Lea` и `f`
+    names.sort_by_key(|name| std::cmp::Reverse(name.chars().count()));
+    names
+}
@@ -0,0 +1,155 @@
+// --------------------------------------------------
+// Встроенная библиотека L-систем и папка пользователя
+// --------------------------------------------------
+
+use super::{LSystemConfig, Lsystem, Parser};
+use std::path::{Path, PathBuf};
+
+/// L-система из встроенной библиотеки.
+///
+/// Текст конфигурации вшит в программу, но разбирается тем же Parser, что и файлы,
+/// поэтому встроенные L-системы не расходятся с форматом.
+pub struct Preset {
+    /// Раздел библиотеки.
+    pub category: &'static str,
+    /// Название для меню.
+    pub name: &'static str,
+    /// Имя файла в папке `l-systems` (по расширению определяется формат).
+    pub file_name: &'static str,
+    /// Текст конфигурации.
+    pub text: &'static str,
+}
+
+impl Preset {
+    /// Разобрать конфигурацию L-системы.
+    pub fn to_config(&self) -> Result<LSystemConfig, Box<dyn std::error::Error>> {
+        Parser::new().parse_text(self.text, self.file_name)
+    }
+}
+
+/// Встроенная L-система из файла папки `l-systems`.
+macro_rules! preset {
+    ($category:expr, $name:expr, $file_name:literal) => {
+        Preset {
+            category: $category,
+            name: $name,
+            file_name: $file_name,
+            text: include_str!(concat!("../../../../l-systems/", $file_name)),
+        }
+    };
+}
+
+const CURVES: &str = "Кривые";
+const PLANTS: &str = "Растения";
+const ISLANDS: &str = "Острова";
+const SPACE_FILLING: &str = "Заполняющие кривые";
+
+/// Разделы встроенной библиотеки (в порядке показа).
+pub const CATEGORIES: [&str; 4] = [CURVES, PLANTS, ISLANDS, SPACE_FILLING];
+
+/// Встроенная библиотека L-систем.
+pub const PRESETS: &[Preset] = &[
+    preset!(CURVES, "Кривая Коха", "кривая-коха.txt"),
+    preset!(CURVES, "Снежинка Коха", "снежинка-коха.txt"),
+    preset!(CURVES, "Кривая дракона", "кривая-дракона.txt"),
+    preset!(
+        CURVES,
+        "Радужная кривая дракона",
+        "радужная-кривая-дракона.txt"
+    ),
+    preset!(CURVES, "Ковёр Серпинского", "ковер-серпинского.txt"),
+    preset!(PLANTS, "Дерево", "дерево.txt"),
+    preset!(PLANTS, "Дерево с именами модулей", "именованное-дерево.txt"),
+    preset!(PLANTS, "Стохастическое дерево", "стохастическое-дерево.txt"),
+    preset!(PLANTS, "Случайное дерево", "случайное-дерево.txt"),
+    preset!(
+        PLANTS,
+        "Параметрическое дерево",
+        "параметрическое-дерево.txt"
+    ),
+    preset!(PLANTS, "Сужающееся дерево", "сужающееся-дерево.txt"),
+    preset!(PLANTS, "Цветное дерево", "цветное-дерево.txt"),
+    preset!(PLANTS, "Контекстное растение", "контекстное-растение.txt"),
+    preset!(PLANTS, "Папоротник", "папоротник.txt"),
+    preset!(PLANTS, "Тянущиеся ростки", "тянущиеся-ростки.txt"),
+    preset!(PLANTS, "Куст 3D", "куст-3d.txt"),
+    preset!(PLANTS, "Дерево с константами", "дерево-с-константами.txt"),
+    preset!(PLANTS, "Цветущее растение", "цветущее-растение.txt"),
+    preset!(
+        ISLANDS,
+        "Квадратный остров Коха",
+        "квадратный-остров-коха.txt"
+    ),
+    preset!(
+        ISLANDS,
+        "Закрашенный остров Коха",
+        "закрашенный-остров-коха.txt"
+    ),
+    preset!(ISLANDS, "Острова и озёра", "острова-и-озёра.txt"),
+    preset!(SPACE_FILLING, "Кривая Гильберта", "кривая-гильберта.txt"),
+    preset!(
+        SPACE_FILLING,
+        "Кривая Гильберта с градиентом",
+        "кривая-гильберта-с-градиентом.txt"
+    ),
+    preset!(SPACE_FILLING, "Кривая Пеано", "кривая-пеано.txt"),
+    preset!(SPACE_FILLING, "Кривая Госпера", "кривая-госпера.txt"),
+];
+
+/// Расширения файлов L-систем, которые показываются из папки пользователя.
+const USER_EXTENSIONS: [&str; 4] = ["txt", "json", "toml", "l"];
+
+/// Папка пользователя с L-системами по умолчанию: `fractals/l-systems` в папке настроек
+/// (`%APPDATA%` в Windows, `$XDG_CONFIG_HOME` или `~/.config` в остальных системах).
+pub fn default_user_dir() -> Option<PathBuf> {
+    let config_dir = if cfg!(windows) {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    };
+    Some(config_dir?.join("fractals").join("l-systems"))
+}
+
+/// Файлы L-систем в папке dir (по имени). Несуществующая папка считается пустой.
+pub fn list_user_dir(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| {
+            path.is_file()
+                && path
+                    .extension()
+                    .and_then(|extension| extension.to_str())
+                    .is_some_and(|extension| {
+                        USER_EXTENSIONS.contains(&extension.to_lowercase().as_str())
+                    })
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+/// Наибольшее число отрезков на миниатюре: миниатюры рисуются заново в каждом кадре.
+const THUMBNAIL_SEGMENTS: f64 = 3000.0;
+/// Сколько итераций вперёд рассматривать для миниатюры.
+const THUMBNAIL_ITERATIONS: usize = 12;
+
+impl Lsystem {
+    /// L-система для миниатюры: построенная до последней итерации, на которой
+    /// по прогнозу роста получается не больше THUMBNAIL_SEGMENTS отрезков.
+    pub fn thumbnail(config: LSystemConfig) -> Self {
+        let mut lsystem = Self::new(config);
+        let iteration = lsystem
+            .predict_growth(THUMBNAIL_ITERATIONS)
+            .into_iter()
+            .take_while(|growth| growth.segments <= THUMBNAIL_SEGMENTS)
+            .last()
+            .map_or(lsystem.iter, |growth| growth.iteration);
+        lsystem.go_to_iteration(iteration);
+        lsystem
+    }
+}
@@ -0,0 +1,51 @@
+// --------------------------------------------------
+// Ход построения L-системы для фоновых потоков
+// --------------------------------------------------
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+/// Ход построения L-системы, за которым можно следить и который можно отменить из другого потока.
+///
+/// Работа измеряется в обработанных модулях: переписанных и переданных чертёжнику.
+#[derive(Debug, Default)]
+pub struct Progress {
+    /// Сколько модулей уже обработано.
+    done: AtomicU64,
+    /// Сколько модулей ожидается обработать (биты f64, оценка по прогнозу роста).
+    total: AtomicU64,
+    /// Запрошена ли отмена.
+    cancelled: AtomicBool,
+}
+
+impl Progress {
+    /// Доля выполненной работы от 0 до 1.
+    pub fn fraction(&self) -> f32 {
+        let total = f64::from_bits(self.total.load(Ordering::Relaxed));
+        if total <= 0.0 {
+            return 0.0;
+        }
+        (self.done.load(Ordering::Relaxed) as f64 / total).min(1.0) as f32
+    }
+
+    /// Попросить построение остановиться.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Была ли запрошена отмена.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Начать отсчёт заново с ожидаемым объёмом работы total.
+    pub(super) fn start(&self, total: f64) {
+        self.done.store(0, Ordering::Relaxed);
+        self.total.store(total.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Отметить count обработанных модулей. Возвращает false, если построение пора прервать.
+    pub(super) fn advance(&self, count: usize) -> bool {
+        self.done.fetch_add(count as u64, Ordering::Relaxed);
+        !self.is_cancelled()
+    }
+}
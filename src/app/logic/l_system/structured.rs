@@ -0,0 +1,394 @@
+// --------------------------------------------------
+// Конфигурация L-системы в форматах JSON и TOML
+// --------------------------------------------------
+
+use super::LSystemConfig;
+use super::color::{self, COLOR_MODE_KEYWORDS, ColorMode};
+use super::lexer::{self, Line, Pos, Span};
+use super::parser::{self, LParseErr, Parser, Section};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/*
+
+Структурированная конфигурация описывает то же, что и текстовый формат, но каждая часть лежит
+в своём поле, поэтому её проще генерировать другими программами:
+```toml
+axiom = "X"
+angle = 25          # угол поворота в градусах
+heading = 90        # начальное направление в градусах
+
+# правила: строки вида `<левая часть> -> <строка модулей>`
+rules = ["X -> F[+X][-X]FX", "F -(0.5)-> FF", "F -(0.5)-> F"]
+
+# именованные константы (необязательны), их можно использовать в выражениях
+[define]
+STEP = 5
+
+# действия: <шаблон> = <операция>
+[actions]
+F = "FORWARD STEP"
+"+" = "ROTATE +"
+"-" = "ROTATE -"
+"[" = "SAVE"
+"]" = "RESTORE"
+
+# переменные (все необязательны)
+[vars]
+width = 1.5
+width_delta = 0.0
+color = [30, 90, 30]
+color_delta = [0, 0, 0]  # каналы могут быть отрицательными
+color_mode = "RGB"       # RGB | HSV | GRADIENT_INDEX | GRADIENT_LENGTH
+hsv_delta = [0, 0, 0]    # тон в градусах, насыщенность и яркость
+gradient = [[0, 0, 0], [0, 128, 0]]  # или с позициями: [[0, [0, 0, 0]], [0.3, [0, 128, 0]]]
+palette = [[0, 0, 0], [0, 128, 0]]
+ignore = "F"
+seed = 42
+```
+Правила можно задать и таблицей `<левая часть> = <строка модулей>` (или списком строк модулей
+для нескольких продукций одного символа): `rules = { X = "F[+X][-X]FX", F = ["FF", "F"] }`.
+Левые части, строки модулей и операции записываются так же, как в текстовом формате, включая
+контекст, условия и веса. JSON-файл содержит объект с теми же полями.
+
+Таблицы правил (секции `[rules.<имя>]` текстового формата) задаются полем `tables`, где у каждой
+таблицы правила в том же виде, что и в `rules`, а расписание - переменной `schedule`:
+```toml
+[tables]
+grow = ["A -> F[+A][-A]FA"]
+bloom = { A = "F[+L][-L]" }
+
+[vars]
+schedule = "grow*4, bloom*"
+```
+Без `schedule` таблицы применяются по кругу в порядке их имён.
+
+*/
+
+/// Структурированная конфигурация L-системы (файлы `.json` и `.toml`).
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StructuredConfig {
+    /// Аксиома.
+    axiom: String,
+    /// Угол поворота в градусах.
+    angle: f32,
+    /// Начальное направление в градусах.
+    heading: f32,
+    /// Правила.
+    #[serde(default)]
+    rules: Rules,
+    /// Таблицы правил по именам.
+    #[serde(default)]
+    tables: BTreeMap<String, Rules>,
+    /// Действия: шаблон модуля и операция.
+    #[serde(default)]
+    actions: BTreeMap<String, String>,
+    /// Именованные константы (как `DEFINE` текстового формата).
+    #[serde(default)]
+    define: BTreeMap<String, f32>,
+    /// Переменные.
+    #[serde(default)]
+    vars: Vars,
+}
+
+/// Правила структурированной конфигурации.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Rules {
+    /// Список правил `<левая часть> -> <строка модулей>`.
+    Lines(Vec<String>),
+    /// Левая часть и одна или несколько строк модулей.
+    Table(BTreeMap<String, Successors>),
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self::Lines(Vec::new())
+    }
+}
+
+/// Правые части продукций одной левой части.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Successors {
+    One(String),
+    Many(Vec<String>),
+}
+
+/// Точка градиента: цвет (цвета стоят на равных расстояниях) или позиция от 0 до 1 и цвет.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum GradientItem {
+    Color([u8; 3]),
+    Stop(f32, [u8; 3]),
+}
+
+/// Переменные структурированной конфигурации (см. присвоения текстового формата).
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Vars {
+    width: Option<f32>,
+    width_delta: Option<f32>,
+    color: Option<[u8; 3]>,
+    color_delta: Option<[i16; 3]>,
+    color_mode: Option<String>,
+    hsv_delta: Option<[f32; 3]>,
+    gradient: Option<Vec<GradientItem>>,
+    palette: Option<Vec<[u8; 3]>>,
+    ignore: Option<String>,
+    seed: Option<u64>,
+    schedule: Option<String>,
+}
+
+impl Parser {
+    /// Разобрать конфигурацию L-системы в формате JSON.
+    pub fn parse_json(&mut self, text: &str) -> Result<LSystemConfig, Box<dyn std::error::Error>> {
+        let structured: StructuredConfig = serde_json::from_str(text)?;
+        self.parse_structured(structured)
+    }
+
+    /// Разобрать конфигурацию L-системы в формате TOML.
+    pub fn parse_toml(&mut self, text: &str) -> Result<LSystemConfig, Box<dyn std::error::Error>> {
+        let structured: StructuredConfig = toml::from_str(text)?;
+        self.parse_structured(structured)
+    }
+
+    /// Собрать конфигурацию из полей структурированного файла.
+    ///
+    /// Правила и действия превращаются в строки текстового формата и разбираются
+    /// так же, как строки секций `[rules]` и `[actions]`. Ошибки собираются по всем полям;
+    /// в них указывается поле, но не позиция: её в исходном файле после serde уже не узнать.
+    fn parse_structured(
+        &mut self,
+        structured: StructuredConfig,
+    ) -> Result<LSystemConfig, Box<dyn std::error::Error>> {
+        let rules = rule_lines("rules", structured.rules);
+        let tables: Vec<(String, Vec<(String, String)>)> = structured
+            .tables
+            .into_iter()
+            .map(|(name, rules)| {
+                let lines = rule_lines(&format!("tables.{}", name), rules);
+                (name, lines)
+            })
+            .collect();
+        let actions: Vec<(String, String)> = structured
+            .actions
+            .into_iter()
+            .map(|(pattern, action)| {
+                (
+                    format!("actions.{}", pattern),
+                    format!("{} -> {}", pattern, action),
+                )
+            })
+            .collect();
+
+        // ошибки собираются по всем полям, а не только до первой
+        let mut errors = Vec::new();
+        self.constants.clear();
+        for (name, value) in structured.define {
+            self.span = Span::new(Pos { line: 1, col: 1 }, name.chars().count());
+            match self.check_constant_name(&name) {
+                Ok(()) => self.constants.push((name, value)),
+                Err(err) => errors.push(field_error("define", err)),
+            }
+        }
+        let rules = tokenize_fields(&rules, &mut errors);
+        let tables: Vec<(&String, Vec<(&str, Line)>)> = tables
+            .iter()
+            .map(|(name, lines)| (name, tokenize_fields(lines, &mut errors)))
+            .collect();
+        let actions = tokenize_fields(&actions, &mut errors);
+        self.names = parser::collect_names(
+            rules
+                .iter()
+                .chain(tables.iter().flat_map(|(_, lines)| lines))
+                .chain(&actions)
+                .map(|(_, line)| line),
+        );
+
+        self.span = Span::new(Pos { line: 1, col: 1 }, structured.axiom.chars().count());
+        let axiom = self.parse_axiom(&structured.axiom).unwrap_or_else(|err| {
+            errors.push(field_error("axiom", err));
+            Vec::new()
+        });
+        let mut config = parser::empty_config(axiom, structured.angle, structured.heading);
+        config.constants = self.constants.clone();
+
+        for (field, line) in &rules {
+            if let Err(err) = self.parse_line(line, Section::Rules, &mut config) {
+                errors.push(field_error(field, err));
+            }
+        }
+        for (name, lines) in &tables {
+            if !parser::is_identifier(name) {
+                errors.push(format!(
+                    "tables: ожидалось <имя таблицы>, получено '{}'",
+                    name
+                ));
+                continue;
+            }
+            let table = config.table_or_insert(name);
+            for (field, line) in lines {
+                if let Err(err) = self.parse_line(line, Section::Table(table), &mut config) {
+                    errors.push(field_error(field, err));
+                }
+            }
+        }
+        for (field, line) in &actions {
+            if let Err(err) = self.parse_line(line, Section::Actions, &mut config) {
+                errors.push(field_error(field, err));
+            }
+        }
+
+        let vars = structured.vars;
+        config.width = vars.width.unwrap_or(config.width);
+        config.width_delta = vars.width_delta.unwrap_or(config.width_delta);
+        if let Some([r, g, b]) = vars.color {
+            config.color = egui::Color32::from_rgb(r, g, b);
+        }
+        if let Some(delta) = vars.color_delta {
+            if delta.iter().all(|channel| (-255..=255).contains(channel)) {
+                config.color_delta = delta;
+            } else {
+                errors.push(format!(
+                    "vars.color_delta: ожидались числа от -255 до 255, получено {:?}",
+                    delta
+                ));
+            }
+        }
+        if let Some(mode) = vars.color_mode {
+            match ColorMode::from_keyword(&mode) {
+                Some(mode) => config.color_mode = mode,
+                None => errors.push(format!(
+                    "vars.color_mode: ожидалось {}, получено {}",
+                    COLOR_MODE_KEYWORDS.join(" | "),
+                    mode
+                )),
+            }
+        }
+        config.hsv_delta = vars.hsv_delta.unwrap_or(config.hsv_delta);
+        if let Some(gradient) = vars.gradient {
+            let rgb = |[r, g, b]: [u8; 3]| egui::Color32::from_rgb(r, g, b);
+            let mut colors = Vec::new();
+            let mut stops = Vec::new();
+            for item in gradient {
+                match item {
+                    GradientItem::Color(color) => colors.push(rgb(color)),
+                    GradientItem::Stop(position, color) => stops.push((position, rgb(color))),
+                }
+            }
+            if stops.is_empty() {
+                config.gradient = color::evenly_spaced(colors);
+            } else if !colors.is_empty() {
+                errors.push(
+                    "vars.gradient: ожидались либо только цвета, либо только точки с позициями"
+                        .into(),
+                );
+            } else if let Some(i) = color::invalid_gradient_stop(&stops) {
+                errors.push(format!(
+                    "vars.gradient: ожидалась позиция от 0 до 1, не меньше предыдущей, получено {}",
+                    stops[i].0
+                ));
+            } else {
+                config.gradient = stops;
+            }
+        }
+        if let Some(palette) = vars.palette {
+            config.palette = palette
+                .into_iter()
+                .map(|[r, g, b]| egui::Color32::from_rgb(r, g, b))
+                .collect();
+        }
+        if let Some(ignore) = vars.ignore {
+            self.span = Span::new(Pos { line: 1, col: 1 }, ignore.chars().count());
+            match self.split_modules(&ignore) {
+                Ok(modules) => {
+                    for (symbol, args) in modules {
+                        if !args.is_empty() {
+                            errors.push(format!(
+                                "vars.ignore: ожидались символы без параметров, получено {}",
+                                ignore
+                            ));
+                            break;
+                        }
+                        config.ignore.insert(symbol);
+                    }
+                }
+                Err(err) => errors.push(field_error("vars.ignore", err)),
+            }
+        }
+        config.seed = vars.seed;
+        self.schedule = None;
+        if let Some(schedule) = vars.schedule {
+            self.span = Span::new(Pos { line: 1, col: 1 }, schedule.chars().count());
+            match self.parse_schedule(&schedule) {
+                Ok((names, cycle)) => self.schedule = Some((self.span, names, cycle)),
+                Err(err) => errors.push(field_error("vars.schedule", err)),
+            }
+        }
+        if let Err(err) = self.resolve_schedule(&mut config) {
+            errors.push(field_error("vars.schedule", err));
+        }
+
+        if !errors.is_empty() {
+            return Err(errors.join("\n").into());
+        }
+        Ok(config)
+    }
+}
+
+/// Правила поля field в виде строк текстового формата вместе с их полями
+/// (`rules[0]` для списка, `rules.X` для таблицы).
+fn rule_lines(field: &str, rules: Rules) -> Vec<(String, String)> {
+    match rules {
+        Rules::Lines(lines) => lines
+            .into_iter()
+            .enumerate()
+            .map(|(i, line)| (format!("{}[{}]", field, i), line))
+            .collect(),
+        Rules::Table(table) => table
+            .into_iter()
+            .flat_map(|(left, successors)| {
+                let successors = match successors {
+                    Successors::One(successor) => vec![successor],
+                    Successors::Many(successors) => successors,
+                };
+                successors.into_iter().map(move |successor| {
+                    (
+                        format!("{}.{}", field, left),
+                        format!("{} -> {}", left, successor),
+                    )
+                })
+            })
+            .collect(),
+    }
+}
+
+/// Разбить значения полей на лексемы: каждое значение - одна строка конфигурации.
+///
+/// Поля, которые не являются одной непустой строкой, пропускаются с ошибкой в errors.
+fn tokenize_fields<'a>(
+    fields: &'a [(String, String)],
+    errors: &mut Vec<String>,
+) -> Vec<(&'a str, Line<'a>)> {
+    let mut lines = Vec::with_capacity(fields.len());
+    for (field, text) in fields {
+        let mut tokenized = lexer::tokenize(text).into_iter();
+        match (tokenized.next(), tokenized.next()) {
+            (Some(line), None) => lines.push((field.as_str(), line)),
+            _ => errors.push(format!(
+                "{}: ожидалась одна непустая строка, получено '{}'",
+                field, text
+            )),
+        }
+    }
+    lines
+}
+
+/// Добавить к ошибке разбора имя поля, в котором она найдена.
+///
+/// Позиция ошибки отсчитывается от начала значения поля, а не файла, поэтому не пишется.
+fn field_error(field: &str, err: LParseErr) -> String {
+    format!("{}: {}", field, err.message())
+}
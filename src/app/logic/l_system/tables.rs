@@ -0,0 +1,179 @@
+// --------------------------------------------------
+// Таблицы правил, сменяющиеся по итерациям (TOL-системы)
+// --------------------------------------------------
+
+use super::{LSystemConfig, Production, Symbol};
+use std::collections::HashMap;
+
+/// Именованная таблица правил (секция `[rules.<имя>]`).
+#[derive(Clone)]
+pub(super) struct RuleTable {
+    /// Имя таблицы.
+    pub(super) name: String,
+    /// Правила таблицы. Они заменяют общие правила тех же символов.
+    pub(super) rules: HashMap<Symbol, Vec<Production>>,
+}
+
+/// Расписание таблиц правил (переменная SCHEDULE).
+#[derive(Clone, Default)]
+pub(super) struct Schedule {
+    /// Номера таблиц для переходов между первыми итерациями.
+    pub(super) steps: Vec<usize>,
+    /// Повторять расписание по кругу. Иначе после конца расписания
+    /// всегда применяется последняя таблица.
+    pub(super) cycle: bool,
+}
+
+impl Schedule {
+    /// Номер таблицы для перехода с уровня level на следующий
+    /// (None, если таблиц нет и применяются только общие правила).
+    pub(super) fn table_at(&self, level: usize) -> Option<usize> {
+        let last = self.steps.len().checked_sub(1)?;
+        let step = if level <= last {
+            level
+        } else if self.cycle {
+            level % self.steps.len()
+        } else {
+            last
+        };
+        Some(self.steps[step])
+    }
+}
+
+impl LSystemConfig {
+    /// Продукции символа при переходе с уровня level на следующий.
+    ///
+    /// Берутся продукции из таблицы по расписанию, а если в ней у символа продукций нет -
+    /// общие продукции (вне таблиц).
+    pub(super) fn productions(&self, symbol: &Symbol, level: usize) -> Option<&Vec<Production>> {
+        self.schedule
+            .table_at(level)
+            .and_then(|table| self.tables[table].rules.get(symbol))
+            .or_else(|| self.rules.get(symbol))
+    }
+
+    /// Все продукции: общие и из всех таблиц.
+    pub(super) fn all_productions(&self) -> impl Iterator<Item = &Production> + Clone {
+        self.rules
+            .values()
+            .chain(self.tables.iter().flat_map(|table| table.rules.values()))
+            .flatten()
+    }
+
+    /// Есть ли у символа продукции среди общих или хотя бы в одной таблице.
+    pub(super) fn has_rules(&self, symbol: &Symbol) -> bool {
+        self.rules.contains_key(symbol)
+            || self
+                .tables
+                .iter()
+                .any(|table| table.rules.contains_key(symbol))
+    }
+
+    /// Номер таблицы с именем name. Если такой таблицы нет, добавляется пустая.
+    pub(super) fn table_or_insert(&mut self, name: &str) -> usize {
+        if let Some(table) = self.tables.iter().position(|table| table.name == name) {
+            return table;
+        }
+        self.tables.push(RuleTable {
+            name: name.to_string(),
+            rules: HashMap::new(),
+        });
+        self.tables.len() - 1
+    }
+
+    /// Записать расписание, как в переменной SCHEDULE: `A*2, B` (по кругу)
+    /// или `A*2, B*` (после `A A B` всегда `B`).
+    pub(super) fn schedule_text(&self) -> String {
+        let mut runs: Vec<(usize, usize)> = Vec::new();
+        for &table in &self.schedule.steps {
+            match runs.last_mut() {
+                Some((last, count)) if *last == table => *count += 1,
+                _ => runs.push((table, 1)),
+            }
+        }
+
+        let last = runs.len().saturating_sub(1);
+        let items: Vec<String> = runs
+            .into_iter()
+            .enumerate()
+            .map(|(i, (table, count))| {
+                let name = &self.tables[table].name;
+                if i == last && !self.schedule.cycle {
+                    format!("{}*", name)
+                } else if count > 1 {
+                    format!("{}*{}", name, count)
+                } else {
+                    name.clone()
+                }
+            })
+            .collect();
+        items.join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{LSystemConfig, Parser, Symbol, format_modules};
+    use super::Schedule;
+
+    fn scheduled(schedule: &str) -> LSystemConfig {
+        let text = format!(
+            "X 90 0\n\
+             [rules]\n\
+             X -> XA\n\
+             Y -> YB\n\
+             [rules.grow]\n\
+             X -> XG\n\
+             [rules.rest]\n\
+             X -> XR\n\
+             [vars]\n\
+             SCHEDULE = {}",
+            schedule
+        );
+        Parser::new()
+            .parse_str(&text)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Правые части продукций символа symbol на уровне level.
+    fn successors(config: &LSystemConfig, symbol: char, level: usize) -> Vec<String> {
+        config
+            .productions(&Symbol::Char(symbol), level)
+            .into_iter()
+            .flatten()
+            .map(|production| format_modules(&production.successor))
+            .collect()
+    }
+
+    #[test]
+    fn schedule_cycles_or_keeps_last_table() {
+        let steps = vec![0, 0, 1];
+        let cycled = Schedule {
+            steps: steps.clone(),
+            cycle: true,
+        };
+        let kept = Schedule {
+            steps,
+            cycle: false,
+        };
+        let cycled: Vec<_> = (0..7).map(|level| cycled.table_at(level)).collect();
+        let kept: Vec<_> = (0..7).map(|level| kept.table_at(level)).collect();
+        assert_eq!(cycled, [0, 0, 1, 0, 0, 1, 0].map(Some));
+        assert_eq!(kept, [0, 0, 1, 1, 1, 1, 1].map(Some));
+        assert_eq!(Schedule::default().table_at(3), None);
+    }
+
+    #[test]
+    fn productions_fall_back_to_common_rules() {
+        let config = scheduled("grow*2, rest");
+        assert_eq!(successors(&config, 'X', 0), ["XG"]);
+        assert_eq!(successors(&config, 'X', 2), ["XR"]);
+        assert_eq!(successors(&config, 'X', 3), ["XG"]);
+        // у Y нет продукций в таблицах - берутся общие
+        assert_eq!(successors(&config, 'Y', 0), ["YB"]);
+        assert_eq!(successors(&config, 'Z', 0), Vec::<String>::new());
+
+        let config = scheduled("grow, rest*");
+        assert_eq!(successors(&config, 'X', 5), ["XR"]);
+    }
+}
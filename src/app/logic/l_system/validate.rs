@@ -0,0 +1,272 @@
+// --------------------------------------------------
+// Проверка конфигурации L-системы
+// --------------------------------------------------
+
+use super::color::ColorMode;
+use super::{Actions, LSystemConfig, ModulePattern, Symbol, format_modules, sorted};
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+/// Подозрительные места в конфигурации L-системы.
+///
+/// Такая конфигурация разбирается и рисуется, но, скорее всего, не так, как задумано.
+#[derive(Debug)]
+pub enum LWarning {
+    /// У символа из аксиомы или правой части продукции нет ни правила, ни действия.
+    UndefinedSymbol(String),
+    /// RESTORE без парного SAVE: место (аксиома или продукция).
+    UnmatchedRestore(String),
+    /// SAVE без парного RESTORE: место и число незакрытых SAVE.
+    UnclosedSave(String, usize),
+    /// Символ с правилами не появляется в строке ни на одной итерации.
+    UnreachableRule(String),
+    /// Пустой диапазон случайного действия: шаблон модуля и операция.
+    EmptyRandomRange(String, String),
+    /// WIDTH_DELTA сводит толщину к минимальной после первой линии: WIDTH и WIDTH_DELTA.
+    WidthCollapse(f32, f32),
+    /// Таблица правил не упоминается в SCHEDULE.
+    UnusedTable(String),
+    /// Режим градиента без цветов градиента: режим COLOR_MODE.
+    EmptyGradient(String),
+    /// Режим HSV меняет только тон серого цвета, у которого тона нет.
+    GreyHue,
+}
+
+impl std::fmt::Display for LWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::UndefinedSymbol(symbol) => {
+                write!(f, "у символа '{}' нет ни правила, ни действия", symbol)
+            }
+            Self::UnmatchedRestore(place) => {
+                write!(f, "{}: RESTORE без парного SAVE", place)
+            }
+            Self::UnclosedSave(place, count) => {
+                write!(f, "{}: {} SAVE без парного RESTORE", place, count)
+            }
+            Self::UnreachableRule(symbol) => write!(
+                f,
+                "правила для '{}' никогда не применяются: символ не появляется в строке",
+                symbol
+            ),
+            Self::EmptyRandomRange(pattern, action) => write!(
+                f,
+                "{} -> {}: пустой диапазон, начало не меньше конца",
+                pattern, action
+            ),
+            Self::WidthCollapse(width, delta) => write!(
+                f,
+                "WIDTH_DELTA = {} уменьшает толщину {} до минимальной 1 уже после первой линии",
+                delta, width
+            ),
+            Self::UnusedTable(name) => {
+                write!(
+                    f,
+                    "таблица [rules.{}] не применяется: её нет в SCHEDULE",
+                    name
+                )
+            }
+            Self::EmptyGradient(mode) => write!(
+                f,
+                "COLOR_MODE = {} без GRADIENT: все линии рисуются цветом COLOR",
+                mode
+            ),
+            Self::GreyHue => write!(
+                f,
+                "HSV_DELTA меняет тон, но у серого цвета COLOR тона нет, и цвет не меняется"
+            ),
+        }
+    }
+}
+
+impl LSystemConfig {
+    /// Проверить конфигурацию на ошибки, которые не мешают разбору, но портят рисунок.
+    pub fn validate(&self) -> Vec<LWarning> {
+        let mut warnings = Vec::new();
+        self.check_symbols(&mut warnings);
+        self.check_brackets(&mut warnings);
+        self.check_reachability(&mut warnings);
+        self.check_random_ranges(&mut warnings);
+        self.check_width(&mut warnings);
+        self.check_tables(&mut warnings);
+        self.check_colors(&mut warnings);
+        warnings
+    }
+
+    /// Символы без правил и действий ничего не делают.
+    ///
+    /// Символы из контекста продукций не считаются: они нужны для сопоставления контекста.
+    fn check_symbols(&self, warnings: &mut Vec<LWarning>) {
+        let productions = self.all_productions();
+        let context: HashSet<&Symbol> = productions
+            .clone()
+            .flat_map(|p| p.left_context.iter().chain(&p.right_context))
+            .map(|pattern| &pattern.symbol)
+            .collect();
+        let used = self
+            .axiom
+            .iter()
+            .map(|module| &module.symbol)
+            .chain(productions.flat_map(|p| p.successor.iter().map(|m| &m.symbol)));
+
+        let undefined: BTreeSet<String> = used
+            .filter(|symbol| {
+                !self.has_rules(symbol)
+                    && !self.actions.contains_key(symbol)
+                    && !context.contains(symbol)
+            })
+            .map(|symbol| symbol.to_string())
+            .collect();
+        warnings.extend(undefined.into_iter().map(LWarning::UndefinedSymbol));
+    }
+
+    /// SAVE и RESTORE должны быть парными в аксиоме и в правой части каждой продукции.
+    fn check_brackets(&self, warnings: &mut Vec<LWarning>) {
+        let mut check = |place: String, symbols: &mut dyn Iterator<Item = &Symbol>| {
+            let mut depth = 0usize;
+            let mut unmatched = false;
+            for symbol in symbols {
+                match self.actions.get(symbol).map(|a| &a.action) {
+                    Some(Actions::Save) => depth += 1,
+                    Some(Actions::Restore) if depth == 0 => unmatched = true,
+                    Some(Actions::Restore) => depth -= 1,
+                    _ => {}
+                }
+            }
+            if unmatched {
+                warnings.push(LWarning::UnmatchedRestore(place.clone()));
+            }
+            if depth > 0 {
+                warnings.push(LWarning::UnclosedSave(place, depth));
+            }
+        };
+
+        check(
+            "аксиома".into(),
+            &mut self.axiom.iter().map(|module| &module.symbol),
+        );
+        let tables = std::iter::once(("", &self.rules))
+            .chain(self.tables.iter().map(|t| (t.name.as_str(), &t.rules)));
+        for (name, rules) in tables {
+            // продукции таблиц помечаются её именем
+            let table = if name.is_empty() {
+                String::new()
+            } else {
+                format!("[rules.{}] ", name)
+            };
+            for symbol in sorted(rules.keys()) {
+                for production in &rules[symbol] {
+                    check(
+                        format!(
+                            "{}{} -> {}",
+                            table,
+                            production.predecessor,
+                            format_modules(&production.successor)
+                        ),
+                        &mut production.successor.iter().map(|m| &m.symbol),
+                    );
+                }
+            }
+        }
+    }
+
+    /// Правила символа, который не появляется ни в аксиоме, ни в правых частях
+    /// применимых правил, никогда не срабатывают.
+    ///
+    /// Правила всех таблиц считаются применимыми на любой итерации, так что с таблицами
+    /// проверка находит не все недостижимые правила.
+    fn check_reachability(&self, warnings: &mut Vec<LWarning>) {
+        let mut successors: HashMap<&Symbol, Vec<&Symbol>> = HashMap::new();
+        for production in self.all_productions() {
+            successors
+                .entry(&production.predecessor.symbol)
+                .or_default()
+                .extend(production.successor.iter().map(|m| &m.symbol));
+        }
+
+        let mut reachable: HashSet<&Symbol> = HashSet::new();
+        let mut queue: Vec<&Symbol> = self.axiom.iter().map(|module| &module.symbol).collect();
+        while let Some(symbol) = queue.pop() {
+            if !reachable.insert(symbol) {
+                continue;
+            }
+            queue.extend(successors.get(symbol).into_iter().flatten());
+        }
+
+        for symbol in sorted(successors.keys().copied()) {
+            if !reachable.contains(symbol) {
+                warnings.push(LWarning::UnreachableRule(symbol.to_string()));
+            }
+        }
+    }
+
+    /// Диапазоны RANDOM с постоянными границами не должны быть пустыми.
+    fn check_random_ranges(&self, warnings: &mut Vec<LWarning>) {
+        for symbol in sorted(self.actions.keys()) {
+            let def = &self.actions[symbol];
+            let empty = match &def.action {
+                Actions::ForwardRandom(min, max) | Actions::MoveRandom(min, max) => {
+                    // границы, зависящие от параметров модуля, заранее не проверить
+                    match (min.as_constant(), max.as_constant()) {
+                        (Some(min), Some(max)) => min >= max,
+                        _ => false,
+                    }
+                }
+                Actions::RotateRandom(_, delta) => *delta <= 0.0,
+                _ => false,
+            };
+            if empty {
+                let pattern = ModulePattern {
+                    symbol: symbol.clone(),
+                    params: def.params.clone(),
+                };
+                warnings.push(LWarning::EmptyRandomRange(
+                    pattern.to_string(),
+                    def.action.to_string(),
+                ));
+            }
+        }
+    }
+
+    /// Толщина не опускается ниже 1, поэтому слишком большое уменьшение WIDTH_DELTA
+    /// делает все линии, кроме первой, одинаково тонкими.
+    ///
+    /// SET_WIDTH задаёт толщину заново, поэтому с ним уменьшение может быть осмысленным.
+    fn check_width(&self, warnings: &mut Vec<LWarning>) {
+        let sets_width = self
+            .actions
+            .values()
+            .any(|def| matches!(def.action, Actions::SetWidth(_)));
+        if self.width_delta < 0.0 && self.width + self.width_delta <= 1.0 && !sets_width {
+            warnings.push(LWarning::WidthCollapse(self.width, self.width_delta));
+        }
+    }
+
+    /// Таблица правил, которой нет в SCHEDULE, никогда не применяется.
+    fn check_tables(&self, warnings: &mut Vec<LWarning>) {
+        for (i, table) in self.tables.iter().enumerate() {
+            if !self.schedule.steps.contains(&i) {
+                warnings.push(LWarning::UnusedTable(table.name.clone()));
+            }
+        }
+    }
+
+    /// Режим цвета должен что-то менять: градиенту нужны цвета, а тону - насыщенность.
+    ///
+    /// Цвет из палитры может быть не серым, поэтому с PALETTE тон не проверяется.
+    fn check_colors(&self, warnings: &mut Vec<LWarning>) {
+        if self.color_mode.is_gradient() && self.gradient.is_empty() {
+            warnings.push(LWarning::EmptyGradient(self.color_mode.to_string()));
+        }
+        let [hue, saturation, _] = self.hsv_delta;
+        let color = self.color;
+        let grey = color.r() == color.g() && color.g() == color.b();
+        if self.color_mode == ColorMode::Hsv
+            && hue != 0.0
+            && saturation <= 0.0
+            && grey
+            && self.palette.is_empty()
+        {
+            warnings.push(LWarning::GreyHue);
+        }
+    }
+}
@@ -0,0 +1,332 @@
+// --------------------------------------------------
+// Запись конфигурации L-системы обратно в текстовый формат
+// --------------------------------------------------
+
+use super::color::{self, ColorMode};
+use super::expr::Expr;
+use super::{
+    Actions, LSystemConfig, Lsystem, ModulePattern, Production, Symbol, is_word_char, sorted,
+};
+use std::collections::HashMap;
+
+/// Исходная запись чисел конфигурации (`360/7`, `STEP*RATIO`).
+///
+/// Числа вычисляются при разборе, но при записи конфигурации обратно пишется выражение,
+/// как в исходном файле. None - число задано не текстом (например, в JSON) или не задано.
+#[derive(Clone, Default)]
+pub(super) struct NumberSources {
+    /// Угол поворота из заголовка.
+    pub(super) rotate_angle: Option<String>,
+    /// Начальное направление из заголовка.
+    pub(super) angle: Option<String>,
+    /// WIDTH.
+    pub(super) width: Option<String>,
+    /// WIDTH_DELTA.
+    pub(super) width_delta: Option<String>,
+    /// Выражения констант `DEFINE` по их именам.
+    pub(super) constants: HashMap<String, String>,
+    /// Веса продукций `-(<вес>)->` по таблице (None - общие правила), символу
+    /// и номеру продукции среди продукций этого символа.
+    pub(super) weights: HashMap<(Option<usize>, Symbol, usize), String>,
+    /// Отклонения `ROTATE RANDOM` по символам действий.
+    pub(super) random_deltas: HashMap<Symbol, String>,
+}
+
+impl LSystemConfig {
+    /// Записать конфигурацию в формате файла, который читает Parser.
+    ///
+    /// Правила, действия и переменные пишутся в своих секциях, поэтому прочитанная обратно
+    /// конфигурация совпадает с исходной (но порядок строк и запись чисел могут поменяться).
+    pub fn to_config_text(&self) -> String {
+        let mut text = String::new();
+
+        // <аксиома> <угол поворота в градусах> <начальное направление в градусах>
+        text += &format!(
+            "{} {} {}\n",
+            self.write_modules(&self.axiom),
+            write_number(self.sources.rotate_angle.as_deref(), self.rotate_angle),
+            write_number(self.sources.angle.as_deref(), self.angle)
+        );
+
+        if !self.constants.is_empty() {
+            text.push('\n');
+            for (name, value) in &self.constants {
+                let source = self.sources.constants.get(name).map(String::as_str);
+                text += &format!("DEFINE {} = {}\n", name, write_number(source, *value));
+            }
+        }
+
+        text += "\n[rules]\n";
+        text += &self.write_rules(None, &self.rules);
+        for (index, table) in self.tables.iter().enumerate() {
+            text += &format!("\n[rules.{}]\n", table.name);
+            text += &self.write_rules(Some(index), &table.rules);
+        }
+
+        text += "\n[actions]\n";
+        for symbol in sorted(self.actions.keys()) {
+            let action = &self.actions[symbol];
+            let pattern = ModulePattern {
+                symbol: symbol.clone(),
+                params: action.params.clone(),
+            };
+            text += &format!(
+                "{} -> {}\n",
+                pattern,
+                self.write_action(symbol, &action.action)
+            );
+        }
+
+        text += "\n[vars]\n";
+        text += &format!(
+            "WIDTH = {}\n",
+            write_number(self.sources.width.as_deref(), self.width)
+        );
+        text += &format!(
+            "WIDTH_DELTA = {}\n",
+            write_number(self.sources.width_delta.as_deref(), self.width_delta)
+        );
+        text += &format!("COLOR = {}\n", write_color(self.color));
+        let [r, g, b] = self.color_delta;
+        text += &format!("COLOR_DELTA = ({}, {}, {})\n", r, g, b);
+        if self.color_mode != ColorMode::Rgb {
+            text += &format!("COLOR_MODE = {}\n", self.color_mode);
+        }
+        if self.hsv_delta != [0.0; 3] {
+            let [hue, saturation, value] = self.hsv_delta;
+            text += &format!("HSV_DELTA = ({}, {}, {})\n", hue, saturation, value);
+        }
+        if !self.gradient.is_empty() {
+            // на равных расстояниях точки пишутся одними цветами, иначе - с позициями
+            let even = color::is_evenly_spaced(&self.gradient);
+            let gradient: Vec<String> = self
+                .gradient
+                .iter()
+                .map(|&(position, color)| {
+                    if even {
+                        write_color(color)
+                    } else {
+                        format!("({}, {})", position, write_color(color))
+                    }
+                })
+                .collect();
+            text += &format!("GRADIENT = [{}]\n", gradient.join(", "));
+        }
+        if !self.palette.is_empty() {
+            let palette: Vec<String> = self.palette.iter().map(|c| write_color(*c)).collect();
+            text += &format!("PALETTE = [{}]\n", palette.join(", "));
+        }
+        if !self.ignore.is_empty() {
+            let ignore: Vec<String> = sorted(&self.ignore)
+                .into_iter()
+                .map(|s| s.to_string())
+                .collect();
+            text += &format!("IGNORE = {}\n", ignore.join(" "));
+        }
+        if let Some(seed) = self.seed {
+            text += &format!("SEED = {}\n", seed);
+        }
+        if !self.tables.is_empty() {
+            text += &format!("SCHEDULE = {}\n", self.schedule_text());
+        }
+
+        text
+    }
+
+    /// Записать правила таблицы table (None - общие правила), по строке на продукцию.
+    fn write_rules(
+        &self,
+        table: Option<usize>,
+        rules: &HashMap<Symbol, Vec<Production>>,
+    ) -> String {
+        let mut text = String::new();
+        for symbol in sorted(rules.keys()) {
+            for (index, production) in rules[symbol].iter().enumerate() {
+                let weight = self.sources.weights.get(&(table, symbol.clone(), index));
+                text += &self.write_production(production, weight.map(String::as_str));
+                text.push('\n');
+            }
+        }
+        text
+    }
+
+    /// Записать продукцию: `[<контекст> <] <модуль> [> <контекст>] [: <условие>] -> <модули>`.
+    ///
+    /// weight - исходная запись веса, если он был задан текстом.
+    fn write_production(&self, production: &Production, weight: Option<&str>) -> String {
+        let mut text = String::new();
+        if !production.left_context.is_empty() {
+            text += &self.write_modules(&production.left_context);
+            text += " < ";
+        }
+        text += &production.predecessor.to_string();
+        if !production.right_context.is_empty() {
+            text += " > ";
+            text += &self.write_modules(&production.right_context);
+        }
+        if let Some(condition) = &production.condition {
+            text += " : ";
+            text += &condition.to_string();
+        }
+        if weight.is_none() && production.weight == 1.0 {
+            text += " ->";
+        } else {
+            text += &format!(" -({})->", write_number(weight, production.weight));
+        }
+        if !production.successor.is_empty() {
+            text.push(' ');
+            text += &self.write_modules(&production.successor);
+        }
+        text
+    }
+
+    /// Записать строку модулей.
+    ///
+    /// Модули пишутся подряд, но пробел ставится там, где при чтении соседние модули
+    /// слились бы: между `-` и `>` (иначе это стрелка), а если есть имена из нескольких
+    /// символов - между буквами и цифрами (иначе `A` и `B` могут слиться в имя `AB`).
+    fn write_modules<T: std::fmt::Display>(&self, modules: &[T]) -> String {
+        let has_names = self
+            .all_productions()
+            .map(|production| &production.predecessor.symbol)
+            .chain(self.actions.keys())
+            .any(|symbol| matches!(symbol, Symbol::Name(_)));
+
+        let mut text = String::new();
+        for module in modules {
+            let module = module.to_string();
+            let prev = text.chars().next_back();
+            let next = module.chars().next();
+            let words =
+                has_names && prev.is_some_and(is_word_char) && next.is_some_and(is_word_char);
+            let arrow = prev == Some('-') && next == Some('>');
+            if words || arrow {
+                text.push(' ');
+            }
+            text += &module;
+        }
+        text
+    }
+
+    /// Записать операцию действия модуля symbol.
+    /// Углы, равные углу поворота из заголовка, пишутся как `+` и `-`.
+    fn write_action(&self, symbol: &Symbol, action: &Actions) -> String {
+        let angle = |expr: &Expr| match expr.as_constant() {
+            Some(value) if value == self.rotate_angle => "+".to_string(),
+            Some(value) if value == -self.rotate_angle => "-".to_string(),
+            _ => expr.to_string(),
+        };
+        match action {
+            Actions::Rotate(x) => format!("ROTATE {}", angle(x)),
+            Actions::Pitch(x) => format!("PITCH {}", angle(x)),
+            Actions::Roll(x) => format!("ROLL {}", angle(x)),
+            Actions::RotateRandom(x, delta) => {
+                let sign = if *x == -self.rotate_angle && *x != 0.0 {
+                    '-'
+                } else {
+                    '+'
+                };
+                let source = self.sources.random_deltas.get(symbol).map(String::as_str);
+                format!("ROTATE RANDOM {} {}", sign, write_number(source, *delta))
+            }
+            // у остальных операций запись для вывода совпадает с записью в файле
+            action => action.to_string(),
+        }
+    }
+}
+
+impl Lsystem {
+    /// Записать конфигурацию L-системы с текущим зерном, чтобы сохранённая
+    /// L-система рисовалась так же, как сейчас.
+    pub fn to_config_text(&self) -> String {
+        LSystemConfig {
+            seed: Some(self.seed),
+            ..self.config.clone()
+        }
+        .to_config_text()
+    }
+}
+
+/// Записать число: исходное выражение source, а если его нет - значение value.
+fn write_number(source: Option<&str>, value: f32) -> String {
+    source.map_or_else(|| value.to_string(), str::to_string)
+}
+
+/// Записать цвет как `(<r>, <g>, <b>)`.
+fn write_color(color: egui::Color32) -> String {
+    format!("({}, {}, {})", color.r(), color.g(), color.b())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{LSystemConfig, Parser, read_fractint_library};
+    use std::path::Path;
+
+    /// Записать конфигурацию, прочитать записанное и записать ещё раз:
+    /// обе записи должны совпасть.
+    fn check_round_trip(name: &str, config: &LSystemConfig) {
+        let text = config.to_config_text();
+        let reparsed = Parser::new().parse_str(&text).unwrap_or_else(|err| {
+            panic!("{}: записанный текст не читается: {}\n{}", name, err, text)
+        });
+        assert_eq!(
+            text,
+            reparsed.to_config_text(),
+            "{}: запись изменилась",
+            name
+        );
+    }
+
+    #[test]
+    fn number_expressions_are_kept() {
+        let config = Parser::new()
+            .parse_str(
+                "X 360/7 0\n\
+                 DEFINE SPREAD = 5\n\
+                 [rules]\n\
+                 X -(1/3)-> F[+X]\n\
+                 X -(2/3)-> FX\n\
+                 [rules.grow]\n\
+                 X -(1/3)-> FF\n\
+                 [actions]\n\
+                 F -> FORWARD 10\n\
+                 + -> ROTATE RANDOM + SPREAD*2\n\
+                 [vars]\n\
+                 SCHEDULE = grow",
+            )
+            .unwrap_or_else(|err| panic!("{}", err));
+        let text = config.to_config_text();
+        for expected in [
+            "X -(1/3)-> F[+X]",
+            "X -(2/3)-> FX",
+            "X -(1/3)-> FF",
+            "+ -> ROTATE RANDOM + SPREAD*2",
+        ] {
+            assert!(text.contains(expected), "нет {:?} в\n{}", expected, text);
+        }
+    }
+
+    #[test]
+    fn examples_round_trip() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("l-systems");
+        let mut checked = 0;
+        for entry in std::fs::read_dir(&dir).unwrap() {
+            let path = entry.unwrap().path();
+            let name = path.display().to_string();
+            if path.extension().is_some_and(|extension| extension == "l") {
+                for entry in read_fractint_library(&path).unwrap() {
+                    let config = entry.to_config().unwrap();
+                    check_round_trip(&format!("{}: {}", name, entry.name), &config);
+                    checked += 1;
+                }
+            } else {
+                let config = Parser::new()
+                    .parse_l_system(&path)
+                    .unwrap_or_else(|err| panic!("{}: {}", name, err));
+                check_round_trip(&name, &config);
+                checked += 1;
+            }
+        }
+        assert!(checked > 0, "в {} нет примеров", dir.display());
+    }
+}
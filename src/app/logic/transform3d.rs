@@ -0,0 +1,170 @@
+// --------------------------------------------------
+// Точки в пространстве и их проецирование на холст
+// --------------------------------------------------
+
+/// Вектор (точка) в 3D пространстве.
+///
+/// Оси совпадают с осями холста: x вправо, y вниз, z от наблюдателя.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Vec3 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Vec3 {
+    pub const ZERO: Self = Self::new(0.0, 0.0, 0.0);
+
+    pub const fn new(x: f32, y: f32, z: f32) -> Self {
+        Self { x, y, z }
+    }
+
+    /// Скалярное произведение.
+    pub fn dot(&self, other: Self) -> f32 {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    /// Векторное произведение.
+    pub fn cross(&self, other: Self) -> Self {
+        Self {
+            x: self.y * other.z - self.z * other.y,
+            y: self.z * other.x - self.x * other.z,
+            z: self.x * other.y - self.y * other.x,
+        }
+    }
+
+    /// Длина вектора.
+    pub fn length(&self) -> f32 {
+        self.dot(*self).sqrt()
+    }
+
+    /// Покомпонентный минимум.
+    pub fn min(&self, other: Self) -> Self {
+        Self::new(
+            self.x.min(other.x),
+            self.y.min(other.y),
+            self.z.min(other.z),
+        )
+    }
+
+    /// Покомпонентный максимум.
+    pub fn max(&self, other: Self) -> Self {
+        Self::new(
+            self.x.max(other.x),
+            self.y.max(other.y),
+            self.z.max(other.z),
+        )
+    }
+
+    /// Повернуть пару ортогональных векторов (a, b) в их плоскости на угол в градусах:
+    /// a поворачивается в сторону b.
+    pub fn rotate_pair(a: Self, b: Self, angle_degrees: f32) -> (Self, Self) {
+        let (sin, cos) = angle_degrees.to_radians().sin_cos();
+        (a * cos + b * sin, b * cos - a * sin)
+    }
+}
+
+impl std::ops::Add for Vec3 {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self::new(self.x + other.x, self.y + other.y, self.z + other.z)
+    }
+}
+
+impl std::ops::Sub for Vec3 {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self::new(self.x - other.x, self.y - other.y, self.z - other.z)
+    }
+}
+
+impl std::ops::Mul<f32> for Vec3 {
+    type Output = Self;
+
+    fn mul(self, k: f32) -> Self {
+        Self::new(self.x * k, self.y * k, self.z * k)
+    }
+}
+
+impl std::ops::Neg for Vec3 {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self::new(-self.x, -self.y, -self.z)
+    }
+}
+
+/// Вид проекции на холст.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    /// Параллельная (ортографическая) проекция.
+    Orthographic,
+    /// Центральная (перспективная) проекция.
+    Perspective,
+}
+
+impl std::fmt::Display for Projection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Projection::Orthographic => write!(f, "Параллельная"),
+            Projection::Perspective => write!(f, "Перспективная"),
+        }
+    }
+}
+
+/// Камера, вращающаяся вокруг центра сцены.
+///
+/// Без поворота камера смотрит вдоль оси z, так что плоские фигуры
+/// проецируются на холст без искажений.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Camera {
+    /// Поворот вокруг вертикальной оси холста (в градусах).
+    pub yaw: f32,
+    /// Поворот вокруг горизонтальной оси холста (в градусах).
+    pub pitch: f32,
+    /// Вид проекции.
+    pub projection: Projection,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            yaw: 0.0,
+            pitch: 0.0,
+            projection: Projection::Orthographic,
+        }
+    }
+}
+
+impl Camera {
+    /// Во сколько раз расстояние от камеры до центра больше радиуса сцены
+    /// (для перспективной проекции).
+    const DISTANCE_FACTOR: f32 = 3.0;
+
+    /// Спроецировать точку на плоскость холста.
+    ///
+    /// center и radius описывают сферу, в которую вписана сцена: камера вращается
+    /// вокруг её центра, а для перспективы отодвигается на несколько радиусов.
+    pub fn project(&self, point: Vec3, center: Vec3, radius: f32) -> egui::Pos2 {
+        let p = point - center;
+
+        // поворот вокруг вертикальной оси, затем вокруг горизонтальной
+        let (sin_yaw, cos_yaw) = self.yaw.to_radians().sin_cos();
+        let x = p.x * cos_yaw + p.z * sin_yaw;
+        let z = p.z * cos_yaw - p.x * sin_yaw;
+        let (sin_pitch, cos_pitch) = self.pitch.to_radians().sin_cos();
+        let y = p.y * cos_pitch - z * sin_pitch;
+        let z = z * cos_pitch + p.y * sin_pitch;
+
+        match self.projection {
+            Projection::Orthographic => egui::Pos2::new(x, y),
+            Projection::Perspective => {
+                let distance = Self::DISTANCE_FACTOR * radius.max(f32::EPSILON);
+                let k = distance / (distance + z).max(f32::EPSILON);
+                egui::Pos2::new(x * k, y * k)
+            }
+        }
+    }
+}
@@ -1,5 +1,3 @@
-use std::f32::EPSILON;
-
 use crate::app::logic::transform2d::Transform2D;
 
 /// Линия для рисования
@@ -26,9 +24,114 @@ impl Line {
     }
 }
 
+/// Закрашенный многоугольник для рисования
+#[derive(Debug, Clone, Default)]
+pub struct Polygon {
+    /// Вершины многоугольника (по порядку обхода, без повтора первой вершины в конце).
+    pub points: Vec<egui::Pos2>,
+    /// Цвет заливки.
+    pub color: egui::Color32,
+}
+
+impl Polygon {
+    pub fn draw(&self, painter: &egui::Painter) {
+        let mut mesh = egui::Mesh::default();
+        for point in &self.points {
+            mesh.colored_vertex(*point, self.color);
+        }
+        for [a, b, c] in triangulate(&self.points) {
+            mesh.add_triangle(a, b, c);
+        }
+        painter.add(egui::Shape::mesh(mesh));
+    }
+}
+
+/// Разбить простой (возможно невыпуклый) многоугольник на треугольники отсечением "ушей".
+///
+/// Возвращает тройки индексов вершин. Если многоугольник самопересекается и "ухо" не находится,
+/// остаток разбивается веером из первой вершины.
+fn triangulate(points: &[egui::Pos2]) -> Vec<[u32; 3]> {
+    // повторяющиеся подряд вершины (в т.ч. замыкающая) дают вырожденные "уши"
+    let mut indices: Vec<usize> = Vec::with_capacity(points.len());
+    for i in 0..points.len() {
+        if indices
+            .last()
+            .is_none_or(|&last| points[last].distance(points[i]) > f32::EPSILON)
+        {
+            indices.push(i);
+        }
+    }
+    while indices.len() > 1
+        && points[indices[0]].distance(points[*indices.last().unwrap()]) <= f32::EPSILON
+    {
+        indices.pop();
+    }
+
+    fn cross(a: egui::Pos2, b: egui::Pos2, c: egui::Pos2) -> f32 {
+        (b - a).x * (c - a).y - (b - a).y * (c - a).x
+    }
+
+    // ориентация обхода: знак удвоенной площади
+    let area: f32 = (0..indices.len())
+        .map(|i| {
+            let a = points[indices[i]];
+            let b = points[indices[(i + 1) % indices.len()]];
+            a.x * b.y - b.x * a.y
+        })
+        .sum();
+    let orientation = area.signum();
+
+    let mut triangles = Vec::new();
+    while indices.len() > 3 {
+        let n = indices.len();
+        let ear = (0..n).find(|&i| {
+            let (a, b, c) = (indices[(i + n - 1) % n], indices[i], indices[(i + 1) % n]);
+            let (pa, pb, pc) = (points[a], points[b], points[c]);
+            // вершина должна быть выпуклой, а внутри треугольника не должно быть других вершин
+            cross(pa, pb, pc) * orientation > 0.0
+                && indices.iter().all(|&j| {
+                    j == a
+                        || j == b
+                        || j == c
+                        || !(cross(pa, pb, points[j]) * orientation >= 0.0
+                            && cross(pb, pc, points[j]) * orientation >= 0.0
+                            && cross(pc, pa, points[j]) * orientation >= 0.0)
+                })
+        });
+        let Some(i) = ear else {
+            break;
+        };
+        triangles.push([
+            indices[(i + n - 1) % n] as u32,
+            indices[i] as u32,
+            indices[(i + 1) % n] as u32,
+        ]);
+        indices.remove(i);
+    }
+    for i in 1..indices.len().saturating_sub(1) {
+        triangles.push([indices[0] as u32, indices[i] as u32, indices[i + 1] as u32]);
+    }
+    triangles
+}
+
 /// Нарисовать коллекцию из линий Line с их масштабированием
 pub fn draw_lines(lines: &[Line], painter: &egui::Painter, area: egui::Rect, margin: f32) {
-    let sys_rect = find_rect(lines);
+    draw_shapes(lines, &[], &[], painter, area, margin);
+}
+
+/// Нарисовать линии Line и закрашенные многоугольники Polygon с их общим масштабированием.
+///
+/// Невидимые линии hidden не рисуются, но учитываются при масштабировании
+/// (например, перемещения чертёжника L-системы без рисования).
+pub fn draw_shapes(
+    lines: &[Line],
+    hidden: &[Line],
+    polygons: &[Polygon],
+    painter: &egui::Painter,
+    area: egui::Rect,
+    margin: f32,
+) {
+    let sys_rect = find_shapes_rect(lines, hidden, polygons);
     if sys_rect.is_none() {
         #[cfg(debug_assertions)]
         println!("No fractal rect");
@@ -53,6 +156,12 @@ pub fn draw_lines(lines: &[Line], painter: &egui::Painter, area: egui::Rect, mar
         line.end = move_tr.apply_to_pos(scale_tr.apply_to_pos(line.end));
         line.draw(painter);
     });
+    polygons.iter().cloned().for_each(|mut polygon| {
+        for point in &mut polygon.points {
+            *point = move_tr.apply_to_pos(scale_tr.apply_to_pos(*point));
+        }
+        polygon.draw(painter);
+    });
 }
 
 /// Найти прямоугольник описывающий узор lines.
@@ -89,10 +198,10 @@ pub fn find_rect(lines: &[Line]) -> Option<egui::Rect> {
     if let Some(pos_min) = pos_min
         && let Some(mut pos_max) = pos_max
     {
-        if (pos_min.x - pos_max.x).abs() < EPSILON {
+        if (pos_min.x - pos_max.x).abs() < f32::EPSILON {
             pos_max.x += 1.0;
         }
-        if (pos_min.y - pos_max.y).abs() < EPSILON {
+        if (pos_min.y - pos_max.y).abs() < f32::EPSILON {
             pos_max.y += 1.0;
         }
         Some(egui::Rect::from_min_max(pos_min, pos_max))
@@ -101,6 +210,35 @@ pub fn find_rect(lines: &[Line]) -> Option<egui::Rect> {
     }
 }
 
+/// Найти прямоугольник описывающий узор из линий lines, невидимых линий hidden
+/// и многоугольников polygons.
+pub fn find_shapes_rect(
+    lines: &[Line],
+    hidden: &[Line],
+    polygons: &[Polygon],
+) -> Option<egui::Rect> {
+    let mut rect = match (find_rect(lines), find_rect(hidden)) {
+        (Some(a), Some(b)) => Some(a.union(b)),
+        (a, b) => a.or(b),
+    };
+    for point in polygons.iter().flat_map(|polygon| &polygon.points) {
+        match &mut rect {
+            Some(rect) => rect.extend_with(*point),
+            None => rect = Some(egui::Rect::from_min_max(*point, *point)),
+        }
+    }
+
+    rect.map(|mut rect| {
+        if rect.width() < f32::EPSILON {
+            rect.max.x += 1.0;
+        }
+        if rect.height() < f32::EPSILON {
+            rect.max.y += 1.0;
+        }
+        rect
+    })
+}
+
 /// Находит преобразование Transform2D дабы разместить область рисования на всю область экрана.
 pub fn get_transform_to_fullscreen(
     screen_rect: egui::Rect,
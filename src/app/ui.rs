@@ -1,6 +1,7 @@
-use egui::Label;
-
 use crate::app::FractalsApp;
+use crate::app::logic;
+use crate::app::logic::l_system;
+use crate::app::logic::transform3d;
 
 // --------------------------------------------------
 // Построение UI приложения
@@ -9,27 +10,154 @@ use crate::app::FractalsApp;
 impl eframe::App for FractalsApp {
     /// Главный цикл UI.
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.poll_lsystem_job();
+        self.poll_thumbnails();
         self.show_top_panel(ctx);
         self.show_left_panel(ctx);
         self.show_bottom_panel(ctx);
         self.show_cental_panel(ctx);
+        self.show_fractint_picker(ctx);
+        self.show_load_errors(ctx);
+        self.show_error(ctx);
+        self.show_warnings(ctx);
     }
 }
 
 impl FractalsApp {
     /// Показать верхную панель приложения.
-    fn show_top_panel(&self, ctx: &egui::Context) {
+    fn show_top_panel(&mut self, ctx: &egui::Context) {
         egui::TopBottomPanel::top("menu_bar").show(ctx, |ui| {
             ui.horizontal(|ui| {
                 ui.menu_button("File", |ui| {
+                    if ui
+                        .add_enabled(self.lsystem.is_some(), egui::Button::new("Save L-system as…"))
+                        .clicked()
+                    {
+                        self.save_lsystem();
+                    }
                     if ui.button("Quit").clicked() {
                         ctx.send_viewport_cmd(egui::ViewportCommand::Close);
                     }
                 });
+                // меню не закрывается от щелчков внутри, чтобы можно было ввести строку поиска
+                egui::containers::menu::MenuButton::new("Presets")
+                    .config(
+                        egui::containers::menu::MenuConfig::new()
+                            .close_behavior(egui::PopupCloseBehavior::CloseOnClickOutside),
+                    )
+                    .ui(ui, |ui| self.show_presets_menu(ui));
             });
         });
     }
 
+    /// Показать меню встроенных L-систем и L-систем из папки пользователя.
+    fn show_presets_menu(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("Поиск:");
+            ui.text_edit_singleline(&mut self.ls_presets_filter);
+        });
+        ui.separator();
+
+        let filter = self.ls_presets_filter.to_lowercase();
+        let mut chosen_preset = None;
+        let mut chosen_file = None;
+        egui::ScrollArea::vertical()
+            .max_height(500.0)
+            .show(ui, |ui| {
+                for category in l_system::CATEGORIES {
+                    let presets: Vec<usize> = (0..l_system::PRESETS.len())
+                        .filter(|&i| {
+                            let preset = &l_system::PRESETS[i];
+                            preset.category == category
+                                && preset.name.to_lowercase().contains(&filter)
+                        })
+                        .collect();
+                    if presets.is_empty() {
+                        continue;
+                    }
+                    ui.label(egui::RichText::new(category).strong());
+                    for i in presets {
+                        let key = logic::ThumbnailKey::Preset(i);
+                        if self.show_preset_entry(ui, key, l_system::PRESETS[i].name) {
+                            chosen_preset = Some(i);
+                        }
+                    }
+                }
+
+                ui.separator();
+                ui.label(egui::RichText::new("Мои L-системы").strong());
+                if let Some(dir) = &self.ls_presets_dir {
+                    ui.weak(dir.display().to_string());
+                }
+                if self.ls_user_presets.is_empty() {
+                    ui.weak("Папка пуста или не найдена");
+                }
+                let files: Vec<std::path::PathBuf> = self
+                    .ls_user_presets
+                    .iter()
+                    .filter(|path| {
+                        path.file_name().is_some_and(|name| {
+                            name.to_string_lossy().to_lowercase().contains(&filter)
+                        })
+                    })
+                    .cloned()
+                    .collect();
+                for path in files {
+                    let name = path
+                        .file_name()
+                        .map(|name| name.to_string_lossy().into_owned())
+                        .unwrap_or_default();
+                    if self.show_preset_entry(ui, logic::ThumbnailKey::File(path.clone()), &name) {
+                        chosen_file = Some(path);
+                    }
+                }
+            });
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            if ui.button("Выбрать папку…").clicked() {
+                self.choose_presets_dir();
+            }
+            if ui.button("Обновить").clicked() {
+                self.refresh_user_presets();
+            }
+        });
+
+        if let Some(i) = chosen_preset {
+            self.load_preset(&l_system::PRESETS[i]);
+            ui.close();
+        }
+        if let Some(path) = chosen_file {
+            self.open_lsystem_file(path);
+            ui.close();
+        }
+    }
+
+    /// Показать строку меню с миниатюрой L-системы и её названием.
+    ///
+    /// Возвращает true, если L-систему выбрали.
+    fn show_preset_entry(
+        &mut self,
+        ui: &mut egui::Ui,
+        key: logic::ThumbnailKey,
+        name: &str,
+    ) -> bool {
+        ui.horizontal(|ui| {
+            let (rect, _) = ui.allocate_exact_size(egui::vec2(48.0, 48.0), egui::Sense::hover());
+            let painter = ui.painter_at(rect);
+            painter.rect_filled(rect, 2.0, egui::Color32::WHITE);
+            match self.thumbnail(key, ui.ctx()) {
+                logic::Thumbnail::Ready(lsystem) => lsystem.draw_thumbnail(&painter, rect),
+                logic::Thumbnail::Pending => {
+                    ui.put(rect, egui::Spinner::new());
+                }
+                logic::Thumbnail::Failed => {}
+            }
+            ui.button(name).clicked()
+        })
+        .inner
+    }
+
     /// Показать левую панель приложения.
     fn show_left_panel(&mut self, ctx: &egui::Context) {
         egui::SidePanel::left("left_panel")
@@ -68,13 +196,130 @@ impl FractalsApp {
                             self.load_lsystem();
                         }
 
-                        if ui.button("Следующая итерация").clicked() {
-                            self.iterate_fractal();
+                        ui.horizontal(|ui| {
+                            let has_prev = self.lsystem.is_some() && self.current_iteration > 1;
+                            if ui
+                                .add_enabled(has_prev, egui::Button::new("Предыдущая итерация"))
+                                .clicked()
+                            {
+                                self.request_lsystem_iteration(self.current_iteration - 1);
+                            }
+                            if ui.button("Следующая итерация").clicked() {
+                                self.iterate_fractal();
+                            }
+                        });
+                        ui.horizontal(|ui| {
+                            ui.label("Итерация:");
+                            ui.add(
+                                egui::DragValue::new(&mut self.ls_target_iteration).range(1..=1000),
+                            );
+                            if ui.button("Перейти").clicked() {
+                                self.request_lsystem_iteration(self.ls_target_iteration);
+                            }
+                        });
+
+                        if let Some((fraction, target)) =
+                            self.ls_job.as_ref().map(|job| (job.fraction(), job.target()))
+                        {
+                            ui.horizontal(|ui| {
+                                ui.add(
+                                    egui::ProgressBar::new(fraction)
+                                        .desired_width(150.0)
+                                        .show_percentage()
+                                        .text(format!("Итерация {}", target)),
+                                );
+                                if ui.button("Отменить").clicked() {
+                                    self.cancel_lsystem_job();
+                                }
+                            });
+                            // пока поток работает, панель должна перерисовываться сама
+                            ctx.request_repaint_after(std::time::Duration::from_millis(50));
                         }
 
-                        if let Some(l) = &self.lsystem {
+                        if let Some(growth) = self.ls_pending_growth {
+                            ui.colored_label(
+                                egui::Color32::RED,
+                                format!(
+                                    "Итерация {} займёт ~{:.0} МБ при лимите {:.0} МБ",
+                                    growth.iteration,
+                                    growth.megabytes(),
+                                    self.ls_memory_budget_mb
+                                ),
+                            );
+                            ui.horizontal(|ui| {
+                                if ui.button("Всё равно выполнить").clicked() {
+                                    self.set_lsystem_iteration(growth.iteration);
+                                }
+                                if ui.button("Отмена").clicked() {
+                                    self.ls_pending_growth = None;
+                                }
+                            });
+                        }
+
+                        let mut new_seed = None;
+                        if let Some(l) = &mut self.lsystem {
+                            ui.horizontal(|ui| {
+                                ui.label("Зерно:");
+                                let mut seed = l.seed();
+                                if ui.add(egui::DragValue::new(&mut seed)).changed() {
+                                    new_seed = Some(seed);
+                                }
+                                if ui.button("Новое зерно").clicked() {
+                                    new_seed = Some(rand::random());
+                                }
+                            });
+
+                            ui.separator();
+                            ui.label("Прогноз роста:");
+                            for growth in l.predict_growth(3) {
+                                ui.label(format!(
+                                    "Итерация {}: {} модулей, {} отрезков, ~{:.1} МБ",
+                                    growth.iteration,
+                                    format_count(growth.modules),
+                                    format_count(growth.segments),
+                                    growth.megabytes()
+                                ));
+                            }
+                            ui.horizontal(|ui| {
+                                ui.label("Лимит памяти (МБ):");
+                                ui.add(
+                                    egui::DragValue::new(&mut self.ls_memory_budget_mb)
+                                        .range(1.0..=65536.0),
+                                );
+                            });
+
+                            ui.separator();
+                            ui.label("Камера (можно вращать мышью на холсте):");
+                            ui.add(
+                                egui::Slider::new(&mut self.ls_camera.yaw, -180.0..=180.0)
+                                    .text("Поворот"),
+                            );
+                            ui.add(
+                                egui::Slider::new(&mut self.ls_camera.pitch, -90.0..=90.0)
+                                    .text("Наклон"),
+                            );
+                            ui.horizontal(|ui| {
+                                for projection in [
+                                    transform3d::Projection::Orthographic,
+                                    transform3d::Projection::Perspective,
+                                ] {
+                                    ui.radio_value(
+                                        &mut self.ls_camera.projection,
+                                        projection,
+                                        projection.to_string(),
+                                    );
+                                }
+                            });
+                            if ui.button("Сбросить камеру").clicked() {
+                                self.ls_camera = transform3d::Camera::default();
+                            }
+
+                            ui.separator();
                             ui.label(l.to_string());
                         }
+                        if let Some(seed) = new_seed {
+                            self.set_lsystem_seed(seed);
+                        }
                     }
 
                     // Настройки для Midpoint Displacement
@@ -133,6 +378,117 @@ impl FractalsApp {
             });
     }
 
+    /// Показать окно выбора L-системы из открытой библиотеки Fractint.
+    fn show_fractint_picker(&mut self, ctx: &egui::Context) {
+        let Some((_, entries)) = &self.ls_fractint else {
+            return;
+        };
+        let mut chosen = None;
+        let mut open = true;
+        egui::Window::new("Библиотека Fractint")
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Поиск:");
+                    ui.text_edit_singleline(&mut self.ls_fractint_filter);
+                });
+                ui.separator();
+
+                let filter = self.ls_fractint_filter.to_lowercase();
+                egui::ScrollArea::vertical().max_height(400.0).show(ui, |ui| {
+                    for (i, entry) in entries.iter().enumerate() {
+                        if entry.name.to_lowercase().contains(&filter)
+                            && ui.selectable_label(false, &entry.name).clicked()
+                        {
+                            chosen = Some(i);
+                        }
+                    }
+                });
+            });
+
+        if let Some(i) = chosen {
+            self.load_fractint_entry(i);
+        }
+        if !open {
+            self.ls_fractint = None;
+        }
+    }
+
+    /// Показать окно с ошибками загрузки L-системы: сообщения и строки файла с подчёркнутыми
+    /// фрагментами, где найдены ошибки.
+    fn show_load_errors(&mut self, ctx: &egui::Context) {
+        let Some(load_errors) = &self.ls_load_errors else {
+            return;
+        };
+        let mut open = true;
+        egui::Window::new(format!("Ошибки в {}", load_errors.file_name))
+            .id(egui::Id::new("ls_load_errors"))
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                egui::ScrollArea::vertical().max_height(400.0).show(ui, |ui| {
+                    for (message, span) in &load_errors.errors {
+                        ui.colored_label(egui::Color32::RED, message);
+                        if let Some(span) = span
+                            && let Some(line) = span
+                                .start
+                                .line
+                                .checked_sub(1)
+                                .and_then(|i| load_errors.lines.get(i))
+                        {
+                            ui.label(underlined_line(line, *span));
+                        }
+                        ui.add_space(4.0);
+                    }
+                });
+            });
+
+        if !open {
+            self.ls_load_errors = None;
+        }
+    }
+
+    /// Показать окно с ошибкой, которая не относится к загружаемому файлу.
+    fn show_error(&mut self, ctx: &egui::Context) {
+        let Some(message) = &self.ls_error else {
+            return;
+        };
+        let mut open = true;
+        egui::Window::new("Ошибка")
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.colored_label(egui::Color32::RED, message);
+            });
+
+        if !open {
+            self.ls_error = None;
+        }
+    }
+
+    /// Показать окно с предупреждениями проверки загруженной L-системы.
+    fn show_warnings(&mut self, ctx: &egui::Context) {
+        if self.ls_warnings.is_empty() {
+            return;
+        }
+        let mut open = true;
+        egui::Window::new("Предупреждения")
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                egui::ScrollArea::vertical().max_height(400.0).show(ui, |ui| {
+                    for warning in &self.ls_warnings {
+                        ui.colored_label(egui::Color32::ORANGE, warning.to_string());
+                    }
+                });
+            });
+
+        if !open {
+            self.ls_warnings.clear();
+        }
+    }
+
     /// Показать нижнюю панель приложения.
     fn show_bottom_panel(&self, ctx: &egui::Context) {
         egui::TopBottomPanel::bottom("bottom_panel").show(ctx, |ui| {
@@ -189,3 +545,44 @@ impl FractalsApp {
         });
     }
 }
+
+/// Строка файла с номером и подчёркнутым фрагментом span.
+fn underlined_line(line: &str, span: l_system::Span) -> egui::text::LayoutJob {
+    let chars: Vec<char> = line.chars().collect();
+    let start = span.start.col.saturating_sub(1).min(chars.len());
+    let end = span.end.col.saturating_sub(1).clamp(start, chars.len());
+    let mut marked: String = chars[start..end].iter().collect();
+    // пустой фрагмент (например, пропущенное значение в конце строки) отмечается пробелом
+    if marked.is_empty() {
+        marked.push(' ');
+    }
+
+    let format = egui::TextFormat {
+        font_id: egui::FontId::monospace(13.0),
+        color: egui::Color32::DARK_GRAY,
+        ..Default::default()
+    };
+    let mut job = egui::text::LayoutJob::default();
+    job.append(&format!("{:>4} | ", span.start.line), 0.0, format.clone());
+    job.append(&chars[..start].iter().collect::<String>(), 0.0, format.clone());
+    job.append(
+        &marked,
+        0.0,
+        egui::TextFormat {
+            color: egui::Color32::RED,
+            underline: egui::Stroke::new(1.5, egui::Color32::RED),
+            ..format.clone()
+        },
+    );
+    job.append(&chars[end..].iter().collect::<String>(), 0.0, format);
+    job
+}
+
+/// Записать (возможно огромное) количество: большие числа - в экспоненциальной форме.
+fn format_count(count: f64) -> String {
+    if count < 1e6 {
+        format!("{:.0}", count)
+    } else {
+        format!("{:.2e}", count)
+    }
+}